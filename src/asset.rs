@@ -1,7 +1,18 @@
-use std::collections::btree_set;
+use std::collections::{btree_set, BTreeMap, BTreeSet};
+use std::str::FromStr;
 
-use bitcoin::OutPoint;
-use rgb::{ConsignmentType, ContractState, InmemConsignment, NodeId, OwnedValue};
+use amplify::Wrapper;
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, Txid};
+use rgb::{
+    data, AtomicValue, Consignment, ConsignmentType, ContractId, ContractState, InmemConsignment,
+    Node, NodeId, NodeOutpoint, OwnedAttachment, OwnedValue, SchemaId, TypedAssignments,
+};
+use stens::AsciiString;
+
+use crate::schema::{FieldType, OwnedRightType};
+use crate::transitions::checked_sum;
+use crate::{RicardianContract, SchemaRegistry, SchemaVersion};
 
 /// RGB121 asset information.
 ///
@@ -27,14 +38,49 @@ use rgb::{ConsignmentType, ContractState, InmemConsignment, NodeId, OwnedValue};
 /// In both (2) and (3) case there is no need to persist the structure; genesis
 /// /consignment should be persisted instead and the structure must be
 /// reconstructed each time from that data upon the launch
+///
+/// The persisted copy contains only the contract state. Information on the
+/// spent and confidential allocations is derived from the consignment history
+/// by [`Asset::try_from`] and is not persisted, thus it is empty for the
+/// structure restored from the persisted copy.
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 #[derive(StrictEncode, StrictDecode)]
-pub struct Asset(ContractState);
+pub struct Asset {
+    state: ContractState,
+    /// Transaction outputs with asset allocations spent by the known state
+    /// transitions
+    #[strict_encoding(skip)]
+    spent: BTreeSet<OutPoint>,
+    /// Asset allocations with known amounts spent by the known state
+    /// transitions
+    #[strict_encoding(skip)]
+    spent_coins: BTreeSet<OwnedValue>,
+    /// Transaction outputs with unspent asset allocations of confidential
+    /// amount
+    #[strict_encoding(skip)]
+    confidential: BTreeSet<OutPoint>,
+}
 
 impl Asset {
-    /// Lists all known allocations for the given bitcoin transaction
-    /// [`OutPoint`]
-    pub fn known_coins(&self) -> btree_set::Iter<OwnedValue> { self.0.owned_values.iter() }
+    /// Lists all known unspent allocations
+    pub fn known_coins(&self) -> impl Iterator<Item = &OwnedValue> {
+        self.owned_values(OwnedRightType::Assets)
+    }
+
+    /// Lists all known unspent inflation rights. State of each right is the
+    /// amount of the asset allowed to be issued by closing it
+    pub fn known_inflation(&self) -> impl Iterator<Item = &OwnedValue> {
+        self.owned_values(OwnedRightType::Inflation)
+    }
+
+    /// Lists all known unspent inflation rights on the given bitcoin
+    /// transaction [`OutPoint`]
+    pub fn outpoint_inflation(&self, outpoint: OutPoint) -> Vec<OwnedValue> {
+        self.known_inflation()
+            .filter(|right| right.seal == outpoint)
+            .cloned()
+            .collect()
+    }
 
     /// Lists all known allocations for the given bitcoin transaction
     /// [`OutPoint`]
@@ -44,6 +90,160 @@ impl Asset {
             .cloned()
             .collect()
     }
+
+    /// Computes sum of the known unspent allocations on the given bitcoin
+    /// transaction outputs, failing if it exceeds the maximal asset amount.
+    ///
+    /// Allocations with confidential amounts (see
+    /// [`Asset::confidential_outpoints`]) are not included into the balance.
+    pub fn balance(&self, outpoints: &BTreeSet<OutPoint>) -> Result<AtomicValue, Error> {
+        checked_sum(
+            self.known_coins()
+                .filter(|coin| outpoints.contains(&coin.seal))
+                .map(|coin| coin.state.value),
+        )
+        .ok_or(Error::AmountOverflow)
+    }
+
+    /// Groups known unspent allocations by bitcoin transaction outputs holding
+    /// them, summing up allocated amounts (failing if any of the sums exceeds
+    /// the maximal asset amount)
+    pub fn holdings_by_outpoint(&self) -> Result<BTreeMap<OutPoint, AtomicValue>, Error> {
+        let mut holdings = BTreeMap::<OutPoint, AtomicValue>::new();
+        for coin in self.known_coins() {
+            let amount = holdings.entry(coin.seal).or_default();
+            *amount = amount
+                .checked_add(coin.state.value)
+                .ok_or(Error::AmountOverflow)?;
+        }
+        Ok(holdings)
+    }
+
+    /// Lists allocations with known amounts which were spent by the state
+    /// transitions known to the consignment
+    pub fn spent_coins(&self) -> btree_set::Iter<'_, OwnedValue> { self.spent_coins.iter() }
+
+    /// Checks whether allocations on the bitcoin transaction output were spent
+    /// by the state transitions known to the consignment
+    pub fn is_spent(&self, outpoint: OutPoint) -> bool { self.spent.contains(&outpoint) }
+
+    /// Lists all known attachments engraved into the asset
+    pub fn known_attachments(&self) -> btree_set::Iter<'_, OwnedAttachment> {
+        self.state.owned_attachments.iter()
+    }
+
+    /// Lists bitcoin transaction outputs with asset allocations which were
+    /// spent by the state transitions known to the consignment
+    pub fn spent_outpoints(&self) -> btree_set::Iter<'_, OutPoint> { self.spent.iter() }
+
+    /// Lists bitcoin transaction outputs with unspent asset allocations, which
+    /// amount is confidential and thus can't be spent
+    pub fn confidential_outpoints(&self) -> btree_set::Iter<'_, OutPoint> {
+        self.confidential.iter()
+    }
+
+    /// Returns id of the asset contract
+    pub fn contract_id(&self) -> ContractId { self.state.contract_id }
+
+    /// Returns id of the asset schema
+    pub fn schema_id(&self) -> SchemaId { self.state.schema_id }
+
+    /// Returns asset name
+    pub fn name(&self) -> Option<AsciiString> {
+        self.genesis_meta(FieldType::Name)
+            .iter()
+            .find_map(data::Revealed::ascii_string)
+    }
+
+    /// Returns asset description, if any
+    pub fn description(&self) -> Option<AsciiString> {
+        self.genesis_meta(FieldType::Description)
+            .iter()
+            .find_map(data::Revealed::ascii_string)
+    }
+
+    /// Returns id of the parent asset, if any
+    pub fn parent_id(&self) -> Option<AsciiString> {
+        self.genesis_meta(FieldType::ParentId)
+            .iter()
+            .find_map(data::Revealed::ascii_string)
+    }
+
+    /// Returns decimal precision of the asset
+    pub fn precision(&self) -> Option<u8> {
+        self.genesis_meta(FieldType::Precision)
+            .iter()
+            .find_map(data::Revealed::u8)
+    }
+
+    /// Returns supply issued with the genesis, failing if the genesis data
+    /// sum up to more than the maximal asset amount
+    pub fn issued_supply(&self) -> Result<AtomicValue, Error> {
+        checked_sum(
+            self.genesis_meta(FieldType::IssuedSupply)
+                .iter()
+                .filter_map(data::Revealed::u64),
+        )
+        .ok_or(Error::AmountOverflow)
+    }
+
+    /// Returns timestamp of the asset genesis
+    pub fn timestamp(&self) -> Option<i64> {
+        self.genesis_meta(FieldType::Timestamp)
+            .iter()
+            .find_map(data::Revealed::i64)
+    }
+
+    /// Returns data blobs embedded into the asset genesis
+    pub fn data(&self) -> Vec<Vec<u8>> {
+        self.genesis_meta(FieldType::Data)
+            .iter()
+            .filter_map(data::Revealed::bytes)
+            .collect()
+    }
+
+    /// Returns format of the data embedded into the asset genesis, if any
+    pub fn data_format(&self) -> Option<u16> {
+        self.genesis_meta(FieldType::DataFormat)
+            .iter()
+            .find_map(data::Revealed::u16)
+    }
+
+    /// Returns version of RGB121 schema used by the asset, or `None` if the
+    /// schema is unknown (which may happen for the assets decoded from the
+    /// persisted data)
+    pub fn version(&self) -> Option<SchemaVersion> {
+        SchemaRegistry::new().version(self.state.schema_id)
+    }
+
+    /// Returns Ricardian contract committed into the asset genesis, if any
+    pub fn ricardian_contract(&self) -> Result<Option<RicardianContract>, Error> {
+        self.genesis_meta(FieldType::RicardianContract)
+            .iter()
+            .find_map(|data| match data {
+                data::Revealed::AsciiString(s) => Some(s),
+                _ => None,
+            })
+            .map(|s| RicardianContract::from_str(s).map_err(|_| Error::InvalidRicardianContract))
+            .transpose()
+    }
+
+    fn owned_values(&self, ty: OwnedRightType) -> impl Iterator<Item = &OwnedValue> {
+        self.state
+            .owned_values
+            .iter()
+            .filter(move |value| value.outpoint.ty == ty as u16)
+    }
+
+    fn genesis_meta(&self, field_type: FieldType) -> &[data::Revealed] {
+        let genesis_id = NodeId::from_inner(self.state.contract_id.into_inner());
+        self.state
+            .metadata
+            .get(&genesis_id)
+            .and_then(|meta| meta.get(&field_type.into()))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
 }
 
 impl<T> TryFrom<&InmemConsignment<T>> for Asset
@@ -53,7 +253,70 @@ where T: ConsignmentType
 
     fn try_from(consignment: &InmemConsignment<T>) -> Result<Self, Self::Error> {
         let state = ContractState::from(consignment);
-        let asset = Asset(state);
+
+        // Seals of all asset allocations, with the flag indicating that the
+        // allocated amount is confidential
+        let mut seals = BTreeMap::<NodeOutpoint, (OutPoint, bool)>::new();
+        let mut coins = BTreeMap::<NodeOutpoint, OwnedValue>::new();
+        let mut consumed = BTreeSet::<NodeOutpoint>::new();
+        let mut process = |node: &dyn Node, txid: Txid| {
+            let node_id = node.node_id();
+            consumed.extend(node.parent_outputs());
+            // Inflation rights are not asset allocations and are tracked by
+            // the contract state only
+            let ty = OwnedRightType::Assets.into();
+            if let Some(TypedAssignments::Value(assignments)) = node.owned_rights_by_type(ty) {
+                for (no, assignment) in assignments.iter().enumerate() {
+                    if let Some(seal) = assignment.revealed_seal() {
+                        let outpoint = OutPoint::new(seal.txid.unwrap_or(txid), seal.vout);
+                        let node_outpoint = NodeOutpoint::new(node_id, ty, no as u16);
+                        let state = assignment.as_revealed_state();
+                        seals.insert(node_outpoint, (outpoint, state.is_none()));
+                        if let Some(state) = state {
+                            coins.insert(node_outpoint, OwnedValue {
+                                seal: outpoint,
+                                outpoint: node_outpoint,
+                                state: *state,
+                            });
+                        }
+                    }
+                }
+            }
+        };
+        process(consignment.genesis(), Txid::all_zeros());
+        for (anchor, bundle) in consignment.anchored_bundles() {
+            for (transition, _) in bundle.revealed_iter() {
+                process(transition, anchor.txid);
+            }
+        }
+        for extension in consignment.state_extensions() {
+            process(extension, Txid::all_zeros());
+        }
+
+        let spent = consumed
+            .iter()
+            .filter_map(|node_outpoint| seals.get(node_outpoint))
+            .map(|(outpoint, _)| *outpoint)
+            .collect();
+        let spent_coins = consumed
+            .iter()
+            .filter_map(|node_outpoint| coins.get(node_outpoint))
+            .cloned()
+            .collect();
+        let confidential = seals
+            .iter()
+            .filter(|(node_outpoint, (_, confidential))| {
+                *confidential && !consumed.contains(node_outpoint)
+            })
+            .map(|(_, (outpoint, _))| *outpoint)
+            .collect();
+
+        let asset = Asset {
+            state,
+            spent,
+            spent_coins,
+            confidential,
+        };
         asset.validate()?;
         Ok(asset)
     }
@@ -61,7 +324,7 @@ where T: ConsignmentType
 
 impl Asset {
     fn validate(&self) -> Result<(), Error> {
-        if self.0.schema_id != crate::schema().schema_id() {
+        if !SchemaRegistry::new().is_supported(self.state.schema_id) {
             Err(Error::WrongSchemaId)?;
         }
         // TODO: Validate the state
@@ -94,4 +357,138 @@ pub enum Error {
     /// not of all epochs referenced in burn or burn & replace operation
     /// history are known from the consignment
     NotAllEpochsExposed,
+
+    /// genesis contains invalid Ricardian contract data
+    InvalidRicardianContract,
+
+    /// sum of the asset amounts exceeds the maximal asset amount
+    AmountOverflow,
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use rgb::RevealSeals;
+    use seals::txout::CloseMethod;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+    use crate::test_helpers::{contract, outpoint, seal, witness_tx, Issuance};
+    use crate::{complete_commitment, embed_transition, finalize_transfer};
+
+    fn issue(ricardian_contract: Option<RicardianContract>) -> Asset {
+        let contract = Issuance {
+            method: CloseMethod::TapretFirst,
+            ricardian_contract,
+            ..Issuance::with(&[(0, 1)])
+        }
+        .contract();
+        Asset::try_from(&contract).unwrap()
+    }
+
+    #[test]
+    fn ricardian_contract() {
+        assert_eq!(issue(None).ricardian_contract(), Ok(None));
+
+        let contract = RicardianContract::with_hash(
+            "Full contract text",
+            Some("https://example.com/contract".parse().unwrap()),
+        );
+        let asset = issue(Some(contract.clone()));
+        assert_eq!(asset.ricardian_contract(), Ok(Some(contract)));
+        assert_eq!(
+            asset
+                .ricardian_contract()
+                .unwrap()
+                .unwrap()
+                .verify_contract_text("Full contract text"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let asset = issue(None);
+        assert_eq!(asset.name().unwrap().as_str(), "Collectible");
+        assert_eq!(asset.description(), None);
+        assert_eq!(asset.precision(), Some(0));
+        assert_eq!(asset.issued_supply(), Ok(1));
+        assert_eq!(asset.data(), Vec::<Vec<u8>>::new());
+        assert_eq!(asset.known_attachments().count(), 0);
+    }
+
+    #[test]
+    fn version() {
+        let mut asset = issue(None);
+        assert_eq!(asset.version(), Some(SchemaVersion::LATEST));
+        asset.state.schema_id = SchemaId::from_inner(Hash::hash(b"foreign"));
+        assert_eq!(asset.version(), None);
+    }
+
+    #[test]
+    fn amount_overflow() {
+        // Persisted data are not validated and may contain allocations
+        // exceeding the maximal asset amount in total
+        let mut asset = Asset::try_from(&contract(&[(0, u64::MAX)])).unwrap();
+        let mut coin = asset.known_coins().next().unwrap().clone();
+        coin.outpoint.no += 1;
+        coin.state.value = 1;
+        asset.state.owned_values.insert(coin);
+        assert_eq!(
+            asset.balance(&bset![outpoint(0)]),
+            Err(Error::AmountOverflow)
+        );
+        assert_eq!(asset.holdings_by_outpoint(), Err(Error::AmountOverflow));
+    }
+
+    #[test]
+    fn persisted_encoding() {
+        let asset = issue(None);
+        // Persisted copy is encoded in the same way as the contract state
+        let data = asset.strict_serialize().unwrap();
+        assert_eq!(data, asset.state.strict_serialize().unwrap());
+        assert_eq!(Asset::strict_deserialize(data), Ok(asset));
+    }
+
+    #[test]
+    fn holdings() {
+        let contract = contract(&[(0, 3), (1, 2), (1, 4)]);
+        let asset = Asset::try_from(&contract).unwrap();
+        assert_eq!(asset.balance(&bset![outpoint(0), outpoint(1)]), Ok(9));
+        assert_eq!(asset.balance(&bset![outpoint(1), outpoint(2)]), Ok(6));
+        assert_eq!(
+            asset.holdings_by_outpoint(),
+            Ok(bmap! { outpoint(0) => 3, outpoint(1) => 6 })
+        );
+        assert_eq!(asset.spent_coins().count(), 0);
+
+        let change = seal(2);
+        let transition = asset
+            .transfer(bset![outpoint(0)], bmap! {}, bmap! { change => 3 })
+            .unwrap();
+        let tx = witness_tx(outpoint(0));
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        embed_transition(&mut psbt, &contract, transition).unwrap();
+        complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+        // The change seal is concealed in the transfer composed for the
+        // beneficiary
+        let mut transfer = finalize_transfer(&psbt, &contract).unwrap();
+        transfer.reveal_seals(&[change]);
+        let asset = Asset::try_from(&transfer).unwrap();
+
+        assert_eq!(
+            asset.holdings_by_outpoint(),
+            Ok(bmap! { outpoint(1) => 6, outpoint(2) => 3 })
+        );
+        assert_eq!(asset.balance(&bset![outpoint(0)]), Ok(0));
+        assert!(asset.is_spent(outpoint(0)));
+        assert!(!asset.is_spent(outpoint(2)));
+        assert_eq!(
+            asset
+                .spent_coins()
+                .map(|coin| (coin.seal, coin.state.value))
+                .collect::<Vec<_>>(),
+            vec![(outpoint(0), 3)]
+        );
+    }
 }
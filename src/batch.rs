@@ -0,0 +1,278 @@
+//! Batch transfers of the asset to many beneficiaries, like airdrops.
+//!
+//! Beneficiaries are grouped into as few state transfers as allowed by the
+//! schema limit on the number of asset assignments per transition. Each of the
+//! transfers spends its own set of transaction outputs from the provided pool,
+//! so all of them can be committed into a single witness transaction.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::OutPoint;
+use rgb::schema::Occurrences;
+use rgb::{seal, AtomicValue, SealEndpoint, Transition};
+
+use crate::schema::{OwnedRightType, TransitionType};
+use crate::selection::{self, CoinSelection, SelectionStrategy};
+use crate::transitions::checked_sum;
+use crate::{Asset, SchemaRegistry, TransitionError};
+
+/// Errors happening during construction of batch transfer
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// batch transfer must have at least one beneficiary
+    NoBeneficiaries,
+
+    /// beneficiary {0} is listed more than once
+    DuplicateBeneficiary(SealEndpoint),
+
+    /// unable to select spent allocations for transfer #{index}: {error}
+    Selection {
+        /// Index of the transfer in the batch
+        index: usize,
+        /// Selection error
+        error: selection::Error,
+    },
+
+    /// asset schema is not one of RGB121 schemata
+    UnknownSchema,
+
+    /// schema allows only {0} asset assignment(s) per transfer, leaving no
+    /// room for a beneficiary next to the change
+    AssignmentLimit(u16),
+}
+
+/// State transfers constructed for a batch of beneficiaries
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BatchTransfer {
+    /// State transitions, which have to be committed into the same witness
+    /// transaction
+    pub transitions: Vec<Transition>,
+    /// Transaction outputs spent by each of the transitions, together with the
+    /// change amounts
+    pub selections: Vec<CoinSelection>,
+    /// Index of the transition paying to each of the beneficiaries
+    pub recipients: BTreeMap<SealEndpoint, usize>,
+}
+
+impl BatchTransfer {
+    /// Lists beneficiaries paid by the transition with the given index
+    pub fn transition_recipients(&self, index: usize) -> impl Iterator<Item = &SealEndpoint> {
+        self.recipients
+            .iter()
+            .filter(move |(_, i)| **i == index)
+            .map(|(beneficiary, _)| beneficiary)
+    }
+}
+
+impl Asset {
+    /// Constructs state transfers paying to all `beneficiaries`, which spend
+    /// transaction outputs from the `pool` and return the change (if any) to
+    /// `change_seal`.
+    ///
+    /// Beneficiaries are grouped in the provided order into as few transitions
+    /// as the schema allows; each transition spends the largest of the pool
+    /// outputs not yet used by the previous transitions.
+    ///
+    /// All the transitions use the same `change_seal`, so their change is
+    /// assigned to the same output under identical seal definitions, and
+    /// revealing the seal reveals the change of all of them.
+    pub fn transfer_batch(
+        &self,
+        beneficiaries: Vec<(SealEndpoint, AtomicValue)>,
+        pool: BTreeSet<OutPoint>,
+        change_seal: seal::Revealed,
+    ) -> Result<BatchTransfer, Error> {
+        let schema = SchemaRegistry::new()
+            .info(self.schema_id())
+            .ok_or(Error::UnknownSchema)?
+            .schema();
+        let occurrences = schema
+            .transitions
+            .get(&TransitionType::Transfer.into())
+            .and_then(|transition| transition.owned_rights.get(&OwnedRightType::Assets.into()))
+            .ok_or(Error::UnknownSchema)?;
+        self.transfer_batch_limited(beneficiaries, pool, change_seal, batch_limit(occurrences)?)
+    }
+
+    pub(crate) fn transfer_batch_limited(
+        &self,
+        beneficiaries: Vec<(SealEndpoint, AtomicValue)>,
+        mut pool: BTreeSet<OutPoint>,
+        change_seal: seal::Revealed,
+        limit: usize,
+    ) -> Result<BatchTransfer, Error> {
+        if beneficiaries.is_empty() {
+            return Err(Error::NoBeneficiaries);
+        }
+        let mut seen = BTreeSet::new();
+        for (beneficiary, _) in &beneficiaries {
+            if !seen.insert(*beneficiary) {
+                return Err(Error::DuplicateBeneficiary(*beneficiary));
+            }
+        }
+
+        let mut batch = BatchTransfer {
+            transitions: vec![],
+            selections: vec![],
+            recipients: bmap! {},
+        };
+        for (index, group) in beneficiaries.chunks(limit).enumerate() {
+            let map_err = |error| Error::Selection { index, error };
+            let amount = checked_sum(group.iter().map(|(_, amount)| *amount))
+                .ok_or(selection::Error::Transition(
+                    TransitionError::OutputsOverflow,
+                ))
+                .map_err(map_err)?;
+            let selection = self
+                .select_coins_from(Some(&pool), amount, SelectionStrategy::LargestFirst)
+                .map_err(map_err)?;
+            let change = if selection.change > 0 {
+                bmap! { change_seal => selection.change }
+            } else {
+                bmap! {}
+            };
+            let payment = group.iter().copied().collect();
+            let transition = self
+                .transfer(selection.inputs.clone(), payment, change)
+                .map_err(|err| map_err(err.into()))?;
+
+            pool.retain(|outpoint| !selection.inputs.contains(outpoint));
+            batch
+                .recipients
+                .extend(group.iter().map(|(beneficiary, _)| (*beneficiary, index)));
+            batch.transitions.push(transition);
+            batch.selections.push(selection);
+        }
+        Ok(batch)
+    }
+}
+
+/// Maximal number of beneficiaries paid by a single transfer with the given
+/// bound on the number of asset assignments, one of which is reserved for the
+/// change
+fn batch_limit(occurrences: &Occurrences) -> Result<usize, Error> {
+    match occurrences.max_value() {
+        max if max < 2 => Err(Error::AssignmentLimit(max)),
+        max => Ok(max as usize - 1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rgb::Node;
+
+    use super::*;
+    use crate::test_helpers::{beneficiary, contract, outpoint, seal};
+
+    /// Asset with allocations of 10, 6 and 3 to the outputs 0 to 2
+    fn asset() -> Asset { Asset::try_from(&contract(&[(0, 10), (1, 6), (2, 3)])).unwrap() }
+
+    #[test]
+    fn grouping() {
+        let asset = asset();
+        let change = seal(99);
+        let beneficiaries = (10..15)
+            .map(|vout| (beneficiary(vout), 3))
+            .collect::<Vec<_>>();
+        let endpoint = |index: usize| &beneficiaries[index].0;
+        let pool = bset![outpoint(0), outpoint(1), outpoint(2)];
+
+        let batch = asset
+            .transfer_batch(beneficiaries.clone(), pool.clone(), change)
+            .unwrap();
+        assert_eq!(batch.transitions.len(), 1);
+        assert_eq!(batch.transition_recipients(0).count(), 5);
+        assert_eq!(batch.selections[0].inputs, bset![outpoint(0), outpoint(1)]);
+        assert_eq!(batch.selections[0].change, 1);
+
+        let batch = asset
+            .transfer_batch_limited(beneficiaries.clone(), pool, change, 2)
+            .unwrap();
+        assert_eq!(batch.transitions.len(), 3);
+        assert_eq!(
+            batch.transition_recipients(1).collect::<BTreeSet<_>>(),
+            bset![endpoint(2), endpoint(3)]
+        );
+        assert_eq!(batch.recipients[endpoint(4)], 2);
+        assert_eq!(
+            batch
+                .selections
+                .iter()
+                .map(|selection| (selection.inputs.clone(), selection.change))
+                .collect::<Vec<_>>(),
+            vec![(bset![outpoint(0)], 4), (bset![outpoint(1)], 0), (bset![outpoint(2)], 0)]
+        );
+    }
+
+    #[test]
+    fn schema_limit() {
+        assert_eq!(
+            batch_limit(&Occurrences::NoneOrMore),
+            Ok(u16::MAX as usize - 1)
+        );
+        assert_eq!(
+            batch_limit(&Occurrences::Once),
+            Err(Error::AssignmentLimit(1))
+        );
+        assert_eq!(
+            batch_limit(&Occurrences::NoneOrUpTo(0)),
+            Err(Error::AssignmentLimit(0))
+        );
+
+        // Each transfer fits the schema bound, including the change
+        let occurrences = Occurrences::NoneOrUpTo(3);
+        let asset = asset();
+        let beneficiaries = (10..15)
+            .map(|vout| (beneficiary(vout), 3))
+            .collect::<Vec<_>>();
+        let batch = asset
+            .transfer_batch_limited(
+                beneficiaries,
+                bset![outpoint(0), outpoint(1), outpoint(2)],
+                seal(99),
+                batch_limit(&occurrences).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(batch.transitions.len(), 3);
+        for transition in &batch.transitions {
+            let assignments = transition
+                .owned_rights_by_type(OwnedRightType::Assets.into())
+                .unwrap()
+                .len() as u16;
+            assert_eq!(occurrences.check(assignments), Ok(()));
+        }
+    }
+
+    #[test]
+    fn failures() {
+        let asset = asset();
+        let change = seal(99);
+        let pool = bset![outpoint(0), outpoint(1)];
+        let duplicate = beneficiary(10);
+
+        assert_eq!(
+            asset.transfer_batch(vec![], pool.clone(), change),
+            Err(Error::NoBeneficiaries)
+        );
+        assert_eq!(
+            asset.transfer_batch(vec![(duplicate, 1), (duplicate, 2)], pool.clone(), change),
+            Err(Error::DuplicateBeneficiary(duplicate))
+        );
+        assert_eq!(
+            asset.transfer_batch_limited(
+                vec![(beneficiary(10), 10), (beneficiary(11), 7)],
+                pool,
+                change,
+                1
+            ),
+            Err(Error::Selection {
+                index: 1,
+                error: selection::Error::InsufficientFunds {
+                    available: 6,
+                    requested: 7
+                }
+            })
+        );
+    }
+}
@@ -1,20 +1,44 @@
 #[macro_use]
+extern crate amplify;
+#[macro_use]
 extern crate clap;
 extern crate serde_crate as serde;
 
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
 use std::str::FromStr;
+use std::{env, fs};
 
-use bitcoin::OutPoint;
-use clap::Parser;
+use amplify::{IoError, Wrapper};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::sha256t;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::rand::{thread_rng, Rng};
+use bitcoin::{OutPoint, Transaction};
+use clap::{ErrorKind, Parser};
 use colored::Colorize;
+use commit_verify::lnpbp4;
 use lnpbp::chain::Chain;
 use rgb::fungible::allocation::{AllocatedValue, OutpointValue, UtxobValue};
-use rgb::{Consignment, Contract, ContractId, IntoRevealedSeal, StateTransfer};
-use rgb121::{Asset, Rgb121};
-use seals::txout::CloseMethod;
+use rgb::psbt::RgbExt;
+use rgb::{
+    seal, Anchor, AtomicValue, AttachmentId, Consignment, ConsignmentType, Contract, ContractId,
+    InmemConsignment, IntoRevealedSeal, Node, NodeOutpoint, RevealSeals, SealEndpoint,
+    StateTransfer, Transition, TransitionBundle,
+};
+use rgb121::{
+    compose_transfer, export_consignment, export_schema, extract_anchor, finalize_transfer,
+    import_consignment, import_schema, verify_schema, Asset, ComposeError, ConsignmentDetails,
+    ConsignmentFormat, CreateError, ExportError, FileAttachment, ImportError, Invoice,
+    InvoiceRequest, MemTxResolver, Provenance, PsbtError, Rgb121, RicardianContract, SchemaFormat,
+    SchemaVerification, SelectionError, SelectionStrategy, Stash, StashError, TransitionError,
+};
+use seals::txout::{CloseMethod, ExplicitSeal};
 use stens::AsciiString;
 use strict_encoding::{StrictDecode, StrictEncode};
+use wallet::psbt::Psbt;
 
 #[derive(Parser, Clone, Debug)]
 #[clap(
@@ -29,6 +53,16 @@ pub struct Opts {
     #[clap(short, long, default_value = "signet", env = "RGB_NETWORK")]
     pub network: Chain,
 
+    /// Report errors as JSON objects to STDERR, suitable for scripting
+    #[clap(long, global = true)]
+    pub json_errors: bool,
+
+    /// Directory of the stash keeping contracts and their history; when
+    /// provided, consignment arguments may be replaced with contract ids, and
+    /// the issued and composed consignments are imported into the stash
+    #[clap(long, global = true, env = "RGB_STASH")]
+    pub stash: Option<PathBuf>,
+
     /// Command to execute
     #[clap(subcommand)]
     pub command: Command,
@@ -50,6 +84,11 @@ pub enum Command {
         /// Asset allocations, in form of <amount>@<txid>:<vout>
         allocations: Vec<OutpointValue>,
 
+        /// Inflation rights allowing secondary issue of the asset, in form of
+        /// <amount>@<txid>:<vout>
+        #[clap(short, long)]
+        inflation: Vec<OutpointValue>,
+
         /// Asset parent ID
         #[clap(long, validator=parent_validator)]
         parent_id: Option<AsciiString>,
@@ -57,16 +96,49 @@ pub enum Command {
         /// Method for seal closing ('tapret1st' or 'opret1st')
         #[clap(short, long, default_value = "tapret1st")]
         method: CloseMethod,
+
+        /// Media files to attach, in form of <path>[:<mime>]; if MIME type is
+        /// omitted it is detected from the file extension
+        #[clap(short, long = "attachment")]
+        attachments: Vec<String>,
+
+        /// Data to embed into the genesis: either a path to a file or
+        /// hex-encoded bytes
+        #[clap(short, long)]
+        data: Vec<String>,
+
+        /// Format of the data embedded with `--data`
+        #[clap(long)]
+        data_format: Option<u16>,
+
+        /// Ricardian contract: either a path to the file with the contract
+        /// text, or a hexadecimal double SHA256 hash of the text, optionally
+        /// followed by a newline and the URL of the text
+        #[clap(long)]
+        contract: Option<String>,
+
+        /// Salt for file attachments; random if not provided
+        #[clap(long)]
+        salt: Option<u64>,
+
+        /// File to save the issued contract to
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Format of the saved contract ('strict', 'bech32', 'yaml' or 'json')
+        #[clap(short, long, default_value = "strict", requires = "output")]
+        format: ConsignmentFormat,
     },
 
     /// Prepares state transition for assets transfer.
     Transfer {
         /// File with state transfer consignment, which endpoints will act as
-        /// inputs.
+        /// inputs, or contract id if the stash is used.
         consignment: PathBuf,
 
-        /// Bitcoin transaction UTXOs which will be spent by the transfer
-        #[clap(short = 'u', long = "utxo", required = true)]
+        /// Bitcoin transaction UTXOs which will be spent by the transfer; if
+        /// omitted, the UTXOs are selected automatically.
+        #[clap(short = 'u', long = "utxo")]
         outpoints: Vec<OutPoint>,
 
         /// List of transfer beneficiaries
@@ -74,17 +146,523 @@ pub enum Command {
         beneficiaries: Vec<UtxobValue>,
 
         /// Change output; one per schema state type.
-        #[clap(short, long)]
+        #[clap(short, long, conflicts_with = "change-seal")]
         change: Vec<AllocatedValue>,
 
+        /// Strategy for automatic selection of the spent UTXOs
+        /// ('largest-first', 'exact-match' or 'minimize-inputs')
+        #[clap(short, long, default_value = "largest-first", conflicts_with = "outpoints")]
+        strategy: SelectionStrategy,
+
+        /// Seal receiving the change from the automatically selected UTXOs
+        #[clap(long, conflicts_with = "outpoints")]
+        change_seal: Option<ExplicitSeal>,
+
         /// File to store state transition transferring assets to the
         /// beneficiaries and onto change outputs.
         output: PathBuf,
     },
+
+    /// Prepares engraving state transition, embedding data into the asset
+    /// while re-allocating the spent assets.
+    Engrave {
+        /// File with state transfer consignment, which endpoints will act as
+        /// inputs, or contract id if the stash is used.
+        consignment: PathBuf,
+
+        /// Bitcoin transaction UTXOs which will be spent by the engraving
+        #[clap(short = 'u', long = "utxo", required = true)]
+        outpoints: Vec<OutPoint>,
+
+        /// Beneficiaries receiving the assets, in form of <amount>@<utxob>
+        #[clap(short, long = "beneficiary")]
+        beneficiaries: Vec<UtxobValue>,
+
+        /// Change output; one per schema state type.
+        #[clap(short, long)]
+        change: Vec<AllocatedValue>,
+
+        /// Data to engrave: either a path to a file or hex-encoded bytes
+        #[clap(short, long)]
+        data: Vec<String>,
+
+        /// Format of the data engraved with `--data`
+        #[clap(long)]
+        data_format: Option<u16>,
+
+        /// File to store engraving state transition.
+        output: PathBuf,
+    },
+
+    /// Prepares secondary issue state transition, closing the inflation
+    /// rights.
+    Inflate {
+        /// File with state transfer consignment, which endpoints hold the
+        /// inflation rights, or contract id if the stash is used.
+        consignment: PathBuf,
+
+        /// Bitcoin transaction UTXOs holding the inflation rights closed by
+        /// the issue
+        #[clap(short = 'u', long = "utxo", required = true)]
+        outpoints: Vec<OutPoint>,
+
+        /// Allocations of the newly issued assets, in form of
+        /// <amount>@<method>:<txid>:<vout>
+        #[clap(required = true)]
+        allocations: Vec<AllocatedValue>,
+
+        /// Inflation rights receiving the rest of the issue allowance, in form
+        /// of <amount>@<method>:<txid>:<vout>
+        #[clap(short, long)]
+        inflation: Vec<AllocatedValue>,
+
+        /// File to store secondary issue state transition.
+        output: PathBuf,
+    },
+
+    /// Composes state transfer consignment for the beneficiary from the state
+    /// transition and its anchor.
+    Compose {
+        /// File with the source consignment, containing history of the spent
+        /// assets, or contract id if the stash is used.
+        consignment: PathBuf,
+
+        /// File with strict-encoded state transition.
+        transition: PathBuf,
+
+        /// File with strict-encoded anchor committing the transition to the
+        /// witness transaction.
+        anchor: PathBuf,
+
+        /// Witness transaction inputs spending the seals closed by the
+        /// transition
+        #[clap(short, long = "input", required = true)]
+        inputs: Vec<u16>,
+
+        /// Format of the composed consignment ('strict', 'bech32', 'yaml' or
+        /// 'json')
+        #[clap(short, long, default_value = "strict")]
+        format: ConsignmentFormat,
+
+        /// File to store the composed consignment.
+        output: PathBuf,
+    },
+
+    /// Extracts the anchor from the signed PSBT, which was used to commit to
+    /// the state transition, and composes state transfer consignment for the
+    /// beneficiary.
+    Finalize {
+        /// File with the source consignment, containing history of the spent
+        /// assets, or contract id if the stash is used.
+        consignment: PathBuf,
+
+        /// File with the signed PSBT, either binary or Base64-encoded.
+        psbt: PathBuf,
+
+        /// Format of the composed consignment ('strict', 'bech32', 'yaml' or
+        /// 'json')
+        #[clap(short, long, default_value = "strict")]
+        format: ConsignmentFormat,
+
+        /// File to store the strict-encoded anchor of the state transition.
+        #[clap(short, long)]
+        anchor: Option<PathBuf>,
+
+        /// File to store the composed consignment.
+        output: PathBuf,
+    },
+
+    /// Converts consignment between different formats
+    Convert {
+        /// File with the source consignment
+        input: PathBuf,
+
+        /// Format of the source consignment ('strict', 'bech32', 'yaml' or
+        /// 'json'); detected automatically if omitted
+        #[clap(long)]
+        from: Option<ConsignmentFormat>,
+
+        /// Format of the converted consignment ('strict', 'bech32', 'yaml' or
+        /// 'json')
+        #[clap(short, long)]
+        to: ConsignmentFormat,
+
+        /// File to save the converted consignment to; if absent, the
+        /// consignment is printed to STDOUT
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Explains the content of a genesis or state transfer consignment
+    Inspect {
+        /// File with the consignment ('strict', 'bech32', 'yaml' or 'json'),
+        /// or contract id if the stash is used
+        consignment: PathBuf,
+
+        /// Output format ('text', 'yaml' or 'json')
+        #[clap(short, long, default_value = "text")]
+        format: InspectFormat,
+    },
+
+    /// Shows provenance of the asset: the genesis followed by all the state
+    /// transitions known to the consignment
+    History {
+        /// File with the consignment ('strict', 'bech32', 'yaml' or 'json'),
+        /// or contract id if the stash is used
+        consignment: PathBuf,
+
+        /// Output format ('text', 'json' or 'dot')
+        #[clap(short, long, default_value = "text")]
+        format: HistoryFormat,
+    },
+
+    /// Operations with RGB121 schemata
+    Schema {
+        #[clap(subcommand)]
+        command: SchemaCommand,
+    },
+
+    /// Operations with the stash of contracts, requiring `--stash` option
+    Stash {
+        #[clap(subcommand)]
+        command: StashCommand,
+    },
+
+    /// Operations with invoices requesting RGB121 assets
+    Invoice {
+        #[clap(subcommand)]
+        command: InvoiceCommand,
+    },
 }
 
-fn main() -> Result<(), String> {
-    let opts = Opts::parse();
+#[derive(Subcommand, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum SchemaCommand {
+    /// Exports RGB121 schema
+    Export {
+        /// Export format ('bech32', 'strict-bin', 'yaml', 'json' or 'markdown')
+        #[clap(short, long, default_value = "bech32")]
+        format: SchemaFormat,
+
+        /// Export subschema prohibiting engraving instead of the root schema
+        #[clap(long)]
+        subschema: bool,
+
+        /// File to save the exported schema to; if absent, the schema is
+        /// printed to STDOUT
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Verifies that the schema from the file (strict-encoded or Bech32) is
+    /// one of RGB121 schemata, listing differences otherwise
+    Verify {
+        /// File containing the schema
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum StashCommand {
+    /// Imports consignment into the stash, merging it with the known history.
+    /// The consignment is validated first; witness transactions of its
+    /// history, which are not known to the stash yet, must be provided with
+    /// `--tx`.
+    Import {
+        /// File with the consignment ('strict', 'bech32', 'yaml' or 'json')
+        consignment: PathBuf,
+
+        /// File with a witness transaction in binary or hex encoding; may be
+        /// repeated
+        #[clap(long = "tx")]
+        txes: Vec<PathBuf>,
+    },
+
+    /// Lists contracts known to the stash
+    List,
+
+    /// Exports consignment with all the history of the contract known to the
+    /// stash
+    Export {
+        /// Contract id
+        contract_id: ContractId,
+
+        /// Format of the exported consignment ('strict', 'bech32', 'yaml' or
+        /// 'json')
+        #[clap(short, long, default_value = "strict")]
+        format: ConsignmentFormat,
+
+        /// File to save the consignment to; if absent, the consignment is
+        /// printed to STDOUT
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum InvoiceCommand {
+    /// Creates invoice for the network specified with `--network` option
+    Create {
+        /// Contract id of the requested asset
+        contract_id: ContractId,
+
+        /// Seal receiving the asset: blinded UTXO, or witness transaction
+        /// output in form of <method>:~:<vout>#<blinding>
+        seal: SealEndpoint,
+
+        /// Requested amount
+        #[clap(short, long, required_unless_present = "token")]
+        amount: Option<AtomicValue>,
+
+        /// Specific token requested instead of an amount, in form of
+        /// <node_id>/<type>/<no>
+        #[clap(short, long, conflicts_with = "amount")]
+        token: Option<NodeOutpoint>,
+
+        /// Time after which the invoice expires, as UNIX timestamp
+        #[clap(short, long)]
+        expiry: Option<i64>,
+
+        /// Id of the attachment which must be engraved into the asset
+        #[clap(long)]
+        attachment: Option<String>,
+
+        /// MIME type of the attachments engraved into the asset
+        #[clap(long)]
+        mime: Option<String>,
+    },
+
+    /// Explains the content of the invoice
+    Decode {
+        /// Invoice URI
+        invoice: Invoice,
+    },
+}
+
+/// Output formats for `inspect` command
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum InspectFormat {
+    /// Colored human-readable text
+    #[display("text")]
+    Text,
+
+    /// YAML document
+    #[display("yaml")]
+    Yaml,
+
+    /// JSON document
+    #[display("json")]
+    Json,
+}
+
+impl FromStr for InspectFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(InspectFormat::Text),
+            "yaml" | "yml" => Ok(InspectFormat::Yaml),
+            "json" => Ok(InspectFormat::Json),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// Output formats for `history` command
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum HistoryFormat {
+    /// Colored human-readable text
+    #[display("text")]
+    Text,
+
+    /// JSON document
+    #[display("json")]
+    Json,
+
+    /// Graphviz DOT graph
+    #[display("dot")]
+    Dot,
+}
+
+impl FromStr for HistoryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(HistoryFormat::Text),
+            "json" => Ok(HistoryFormat::Json),
+            "dot" | "graphviz" => Ok(HistoryFormat::Dot),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// Errors reported by the command-line tool. Each error class terminates the
+/// process with a distinct exit code, see [`Error::exit_code`].
+#[derive(Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// {0}
+    Usage(String),
+
+    /// I/O error: {0}
+    #[from(io::Error)]
+    Io(IoError),
+
+    /// invalid strict-encoded data: {0}
+    Encoding(strict_encoding::Error),
+
+    /// unable to import consignment or schema: {0}
+    #[from]
+    Import(ImportError),
+
+    /// unable to export consignment or schema: {0}
+    #[from]
+    Export(ExportError),
+
+    /// serialization failure: {0}
+    Serialization(String),
+
+    /// unable to create contract: {0}
+    #[from]
+    Create(CreateError),
+
+    /// invalid RGB121 asset: {0}
+    #[from]
+    Asset(rgb121::Error),
+
+    /// unable to construct state transition: {0}
+    #[from]
+    Transition(TransitionError),
+
+    /// unable to select spent UTXOs: {0}
+    #[from]
+    Selection(SelectionError),
+
+    /// unable to compose consignment: {0}
+    #[from]
+    Compose(ComposeError),
+
+    /// invalid PSBT: {0}
+    InvalidPsbt(String),
+
+    /// unable to process RGB data in PSBT: {0}
+    #[from]
+    Psbt(PsbtError),
+
+    /// {0}
+    #[from]
+    SchemaMismatch(SchemaVerification),
+
+    /// stash failure: {0}
+    #[from]
+    Stash(StashError),
+}
+
+impl From<strict_encoding::Error> for Error {
+    fn from(err: strict_encoding::Error) -> Self {
+        match err {
+            strict_encoding::Error::Io(err) => Error::Io(err),
+            err => Error::Encoding(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self { Error::Serialization(err.to_string()) }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self { Error::Serialization(err.to_string()) }
+}
+
+impl Error {
+    /// Exit code of the process terminated with the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::Io(_) => 3,
+            Error::Encoding(_) => 4,
+            Error::Import(_) => 5,
+            Error::Export(_) => 6,
+            Error::Serialization(_) => 7,
+            Error::Create(_) => 8,
+            Error::Asset(_) => 9,
+            Error::Transition(_) => 10,
+            Error::Selection(_) => 11,
+            Error::Compose(_) => 12,
+            Error::InvalidPsbt(_) => 13,
+            Error::Psbt(_) => 14,
+            Error::SchemaMismatch(_) => 15,
+            Error::Stash(_) => 16,
+        }
+    }
+
+    /// Machine-readable name of the error class
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Usage(_) => "usage",
+            Error::Io(_) => "io",
+            Error::Encoding(_) => "encoding",
+            Error::Import(_) => "import",
+            Error::Export(_) => "export",
+            Error::Serialization(_) => "serialization",
+            Error::Create(_) => "create",
+            Error::Asset(_) => "asset",
+            Error::Transition(_) => "transition",
+            Error::Selection(_) => "selection",
+            Error::Compose(_) => "compose",
+            Error::InvalidPsbt(_) => "invalid_psbt",
+            Error::Psbt(_) => "psbt",
+            Error::SchemaMismatch(_) => "schema_mismatch",
+            Error::Stash(_) => "stash",
+        }
+    }
+}
+
+fn main() {
+    let opts = match Opts::try_parse() {
+        Ok(opts) => opts,
+        // Clap does not know yet whether errors must be reported as JSON
+        Err(err)
+            if !matches!(
+                err.kind(),
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+            ) && env::args_os().any(|arg| arg == "--json-errors") =>
+        {
+            report(Error::Usage(err.to_string().trim_end().to_owned()), true)
+        }
+        Err(err) => err.exit(),
+    };
+    let json_errors = opts.json_errors;
+
+    match run(opts) {
+        Ok(()) => {}
+        // Output was closed by the reader, like `head`
+        Err(Error::Io(err)) if *err.as_inner() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => report(err, json_errors),
+    }
+}
+
+/// Prints the error and terminates the process with its exit code
+fn report(err: Error, json_errors: bool) -> ! {
+    if json_errors {
+        let report = serde_json::json!({
+            "error": err.kind(),
+            "code": err.exit_code(),
+            "message": err.to_string(),
+        });
+        eprintln!("{}", report);
+    } else {
+        eprintln!("{} {}", "Error:".bright_red(), err);
+    }
+    exit(err.exit_code())
+}
+
+fn run(opts: Opts) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let stash = opts.stash.as_ref().map(Stash::open).transpose()?;
+    let stash = stash.as_ref();
 
     match opts.command {
         Command::Issue {
@@ -93,23 +671,45 @@ fn main() -> Result<(), String> {
             precision,
             parent_id,
             allocations,
+            inflation,
             method,
+            attachments,
+            data,
+            data_format,
+            contract,
+            salt,
+            output,
+            format,
         } => {
+            let attachments = attachments
+                .iter()
+                .map(|arg| file_attachment(arg, salt))
+                .collect::<Result<Vec<_>, _>>()?;
+            let attachment_paths = attachments
+                .iter()
+                .map(|attachment| attachment.file_path.clone())
+                .collect::<Vec<_>>();
+            let data = data
+                .iter()
+                .map(|arg| data_blob(arg))
+                .collect::<Result<_, _>>()?;
+            let ricardian_contract = contract.as_deref().map(ricardian_contract).transpose()?;
             let contract = Contract::create_rgb121(
                 opts.network,
                 name,
                 description,
                 precision,
                 parent_id,
-                vec![],
-                vec![],
+                attachments,
+                data,
+                data_format,
                 allocations,
+                inflation,
                 method,
-            )
-            .expect("create rgb21 contract failed");
+                ricardian_contract,
+            )?;
 
-            let _asset =
-                Asset::try_from(&contract).expect("create_rgb121 does not match RGB121 schema");
+            let _asset = Asset::try_from(&contract)?;
 
             eprintln!(
                 "{} {}\n",
@@ -117,17 +717,32 @@ fn main() -> Result<(), String> {
                 contract.contract_id().to_string().bright_yellow()
             );
 
+            if let Some(stash) = stash {
+                stash_consignment(Some(stash), &contract, &MemTxResolver::default())?;
+                for path in attachment_paths {
+                    stash.store_attachment(&fs::read(path)?)?;
+                }
+            }
+
+            if let Some(output) = output {
+                let data = export_consignment(&contract, format)?;
+                fs::write(&output, data)?;
+                eprintln!(
+                    "{} {}",
+                    "Contract saved to".bright_green(),
+                    output.display()
+                );
+                return Ok(());
+            }
+
             eprintln!("{}", "Contract YAML:".bright_green());
-            eprintln!("{}", serde_yaml::to_string(contract.genesis()).unwrap());
+            eprintln!("{}", serde_yaml::to_string(contract.genesis())?);
 
             eprintln!("{}", "Contract JSON:".bright_green());
-            println!("{}\n", serde_json::to_string(contract.genesis()).unwrap());
+            writeln!(out, "{}\n", serde_json::to_string(contract.genesis())?)?;
 
             eprintln!("{}", "Contract source:".bright_green());
-            println!("{}\n", contract);
-
-            // eprintln!("{}", "Asset details:".bright_green());
-            // eprintln!("{}\n", serde_yaml::to_string(&asset).unwrap());
+            writeln!(out, "{}\n", contract)?;
         }
 
         Command::Transfer {
@@ -135,11 +750,60 @@ fn main() -> Result<(), String> {
             outpoints,
             beneficiaries,
             change,
+            strategy,
+            change_seal,
             output,
         } => {
-            let transfer = StateTransfer::strict_file_load(consignment).unwrap();
+            let transfer = load_consignment(stash, &consignment)?;
 
-            let asset = Asset::try_from(&transfer).unwrap();
+            let asset = Asset::try_from(&transfer)?;
+
+            let beneficiaries = beneficiaries
+                .into_iter()
+                .map(|v| (v.seal_confidential.into(), v.value))
+                .collect();
+            let transition = if outpoints.is_empty() {
+                let change_seal = change_seal.ok_or_else(|| {
+                    Error::Usage(s!("either --utxo or --change-seal must be provided"))
+                })?;
+                let (transition, selection) =
+                    asset.transfer_selected(beneficiaries, change_seal.into(), strategy)?;
+                eprintln!(
+                    "{} {} {} {}",
+                    "Selected".bright_green(),
+                    selection
+                        .inputs
+                        .iter()
+                        .map(OutPoint::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                        .bright_yellow(),
+                    "with change".bright_green(),
+                    selection.change.to_string().bright_yellow()
+                );
+                transition
+            } else {
+                let change = change
+                    .into_iter()
+                    .map(|v| (v.into_revealed_seal(), v.value))
+                    .collect();
+                let outpoints = outpoints.into_iter().collect();
+                asset.transfer(outpoints, beneficiaries, change)?
+            };
+
+            save_transition(&transition, output)?;
+        }
+
+        Command::Engrave {
+            consignment,
+            outpoints,
+            beneficiaries,
+            change,
+            data,
+            data_format,
+            output,
+        } => {
+            let asset = Asset::try_from(&load_consignment(stash, &consignment)?)?;
 
             let beneficiaries = beneficiaries
                 .into_iter()
@@ -149,20 +813,653 @@ fn main() -> Result<(), String> {
                 .into_iter()
                 .map(|v| (v.into_revealed_seal(), v.value))
                 .collect();
+            let data = data
+                .iter()
+                .map(|arg| data_blob(arg))
+                .collect::<Result<_, _>>()?;
             let outpoints = outpoints.into_iter().collect();
-            let transition = asset.transfer(outpoints, beneficiaries, change).unwrap();
+            let transition = asset.engrave(outpoints, data, data_format, beneficiaries, change)?;
+
+            save_transition(&transition, output)?;
+        }
+
+        Command::Inflate {
+            consignment,
+            outpoints,
+            allocations,
+            inflation,
+            output,
+        } => {
+            let asset = Asset::try_from(&load_consignment(stash, &consignment)?)?;
+            let inflation = inflation.into_iter().map(|v| (v.seal, v.value)).collect();
+            let outpoints = outpoints.into_iter().collect();
+            let transition = asset.inflate(outpoints, inflation, allocations)?;
+
+            save_transition(&transition, output)?;
+        }
+
+        Command::Compose {
+            consignment,
+            transition,
+            anchor,
+            inputs,
+            format,
+            output,
+        } => {
+            let source = load_consignment(stash, &consignment)?;
+            let transition = Transition::strict_file_load(transition)?;
+            let anchor = Anchor::<lnpbp4::MerkleProof>::strict_file_load(anchor)?;
+            let inputs = inputs.into_iter().collect();
+            let bundle = TransitionBundle::try_from(bmap! { transition => inputs })
+                .map_err(|_| ComposeError::EmptyBundle)?;
+
+            let seals = known_seals(&source, &bundle);
+            let transfer = compose_transfer(&source, anchor, bundle)?;
+            fs::write(&output, export_consignment(&transfer, format)?)?;
+            // The witness transaction is not known yet, and the history must
+            // be already known to the stash
+            stash_transfer(stash, &transfer, &seals, &MemTxResolver::default())?;
+
+            eprintln!(
+                "{} {} {}",
+                transfer
+                    .anchored_bundles()
+                    .count()
+                    .to_string()
+                    .bright_yellow(),
+                "anchored bundles and endpoints".bright_green(),
+                transfer
+                    .endpoints()
+                    .map(|(_, seal)| seal.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            writeln!(out, "{}", "Success".bold().bright_green())?;
+        }
+
+        Command::Finalize {
+            consignment,
+            psbt,
+            format,
+            anchor,
+            output,
+        } => {
+            let source = load_consignment(stash, &consignment)?;
+            let psbt = load_psbt(&psbt)?;
+
+            if let Some(path) = anchor {
+                extract_anchor(&psbt)?
+                    .into_merkle_proof(source.contract_id())
+                    .map_err(|_| PsbtError::UnknownContract(source.contract_id()))?
+                    .strict_file_save(path)?;
+            }
+            let contract_id = source.contract_id();
+            let bundle = Psbt::from(psbt.clone())
+                .rgb_bundles()
+                .map_err(PsbtError::from)?
+                .remove(&contract_id)
+                .ok_or(PsbtError::NoBundle(contract_id))?;
+            let seals = known_seals(&source, &bundle);
+            let transfer = finalize_transfer(&psbt, &source)?;
+            fs::write(&output, export_consignment(&transfer, format)?)?;
+            let resolver = MemTxResolver::with([Psbt::from(psbt.clone()).into_unsigned_tx()]);
+            stash_transfer(stash, &transfer, &seals, &resolver)?;
+
+            eprintln!(
+                "{} {} {}",
+                "Witness transaction".bright_green(),
+                Psbt::from(psbt).to_txid().to_string().bright_yellow(),
+                "is committed to the state transfer".bright_green()
+            );
+            writeln!(out, "{}", "Success".bold().bright_green())?;
+        }
+
+        Command::Convert {
+            input,
+            from,
+            to,
+            output,
+        } => {
+            let data = fs::read(input)?;
+            let consignment: StateTransfer = import_consignment(&data, from)?;
+            let data = export_consignment(&consignment, to)?;
+            match output {
+                Some(path) => fs::write(path, data),
+                None => io::stdout().write_all(&data),
+            }?;
+        }
+
+        Command::Inspect {
+            consignment,
+            format,
+        } => {
+            let consignment = load_consignment(stash, &consignment)?;
+            let details = ConsignmentDetails::with(&consignment)?;
+            match format {
+                InspectFormat::Text => print_details(&mut out, &details)?,
+                InspectFormat::Yaml => writeln!(out, "{}", serde_yaml::to_string(&details)?)?,
+                InspectFormat::Json => {
+                    writeln!(out, "{}", serde_json::to_string_pretty(&details)?)?
+                }
+            }
+        }
+
+        Command::History {
+            consignment,
+            format,
+        } => {
+            let consignment = load_consignment(stash, &consignment)?;
+            let provenance = Provenance::with(&consignment)?;
+            match format {
+                HistoryFormat::Text => print_provenance(&mut out, &provenance)?,
+                HistoryFormat::Json => {
+                    writeln!(out, "{}", serde_json::to_string_pretty(&provenance)?)?
+                }
+                HistoryFormat::Dot => write!(out, "{}", provenance.to_dot())?,
+            }
+        }
+
+        Command::Schema {
+            command:
+                SchemaCommand::Export {
+                    format,
+                    subschema,
+                    output,
+                },
+        } => {
+            let schema = if subschema { rgb121::subschema() } else { rgb121::schema() };
+            let data = export_schema(&schema, format)?;
+            match output {
+                Some(path) => fs::write(path, data),
+                None => io::stdout().write_all(&data),
+            }?;
+        }
+
+        Command::Stash {
+            command: StashCommand::Import { consignment, txes },
+        } => {
+            let stash = require_stash(stash)?;
+            let consignment: StateTransfer = import_consignment(&fs::read(consignment)?, None)?;
+            let resolver = MemTxResolver::with(
+                txes.iter()
+                    .map(|path| load_tx(path))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+            stash_consignment(Some(stash), &consignment, &resolver)?;
+            writeln!(
+                out,
+                "{} {}",
+                "Imported contract".bright_green(),
+                consignment.contract_id().to_string().bright_yellow()
+            )?;
+        }
+
+        Command::Stash {
+            command: StashCommand::List,
+        } => {
+            let stash = require_stash(stash)?;
+            for contract_id in stash.contract_ids()? {
+                let asset = stash.asset(contract_id)?;
+                writeln!(
+                    out,
+                    "{} {}",
+                    contract_id.to_string().bright_yellow(),
+                    asset
+                        .name()
+                        .map(|name| name.to_string())
+                        .unwrap_or_default()
+                )?;
+            }
+        }
 
-            transition.strict_file_save(output).unwrap();
-            //consignment.strict_file_save(output).unwrap();
+        Command::Stash {
+            command:
+                StashCommand::Export {
+                    contract_id,
+                    format,
+                    output,
+                },
+        } => {
+            let consignment: StateTransfer = require_stash(stash)?.consignment(contract_id)?;
+            let data = export_consignment(&consignment, format)?;
+            match output {
+                Some(path) => fs::write(path, data),
+                None => io::stdout().write_all(&data),
+            }?;
+        }
 
-            println!("{}", serde_yaml::to_string(&transition).unwrap());
-            println!("{}", "Success".bold().bright_green());
+        Command::Invoice {
+            command:
+                InvoiceCommand::Create {
+                    contract_id,
+                    seal,
+                    amount,
+                    token,
+                    expiry,
+                    attachment,
+                    mime,
+                },
+        } => {
+            let request = match (amount, token) {
+                (_, Some(token)) => InvoiceRequest::Token(token),
+                (Some(amount), None) => InvoiceRequest::Amount(amount),
+                (None, None) => {
+                    return Err(Error::Usage(s!(
+                        "either --amount or --token must be provided"
+                    )))
+                }
+            };
+            let mut invoice = Invoice::with(contract_id, request, seal, opts.network);
+            invoice.expiry = expiry;
+            invoice.attachment = attachment
+                .map(|id| {
+                    sha256t::Hash::from_hex(&id)
+                        .map(AttachmentId::from_inner)
+                        .map_err(|_| Error::Usage(format!("invalid attachment id '{}'", id)))
+                })
+                .transpose()?;
+            invoice.mime = mime;
+            writeln!(out, "{}", invoice)?;
+        }
+
+        Command::Invoice {
+            command: InvoiceCommand::Decode { invoice },
+        } => print_invoice(&mut out, &invoice)?,
+
+        Command::Schema {
+            command: SchemaCommand::Verify { file },
+        } => {
+            let data = fs::read(file)?;
+            let schema = import_schema(&data)?;
+            let verification = verify_schema(&schema);
+            if !verification.is_rgb121() {
+                return Err(verification.into());
+            }
+            writeln!(out, "{}", verification.to_string().bright_green())?;
         }
     }
 
     Ok(())
 }
 
+fn print_details(out: &mut impl Write, details: &ConsignmentDetails) -> io::Result<()> {
+    let meta = &details.metadata;
+    writeln!(
+        out,
+        "{} {}",
+        "Contract ID:".bright_green(),
+        details.contract_id.to_string().bright_yellow()
+    )?;
+    writeln!(
+        out,
+        "{} {} ({})",
+        "Schema:".bright_green(),
+        details.schema_id,
+        details.version
+    )?;
+    let fields = [
+        ("Name", meta.name.clone()),
+        ("Description", meta.description.clone()),
+        (
+            "Ricardian contract",
+            match meta.invalid_ricardian_contract {
+                true => Some(s!("invalid")),
+                false => meta.ricardian_contract.clone(),
+            },
+        ),
+        ("Precision", meta.precision.map(|p| p.to_string())),
+        ("Issued supply", Some(meta.issued_supply.to_string())),
+        ("Timestamp", meta.timestamp.map(|t| t.to_string())),
+        ("Parent ID", meta.parent_id.clone()),
+        ("Data format", meta.data_format.map(|f| f.to_string())),
+    ];
+    for (title, value) in fields {
+        if let Some(value) = value {
+            writeln!(out, "{} {}", format!("{}:", title).bright_green(), value)?;
+        }
+    }
+    for data in &meta.data {
+        writeln!(out, "{} {}", "Data:".bright_green(), data)?;
+    }
+
+    writeln!(out, "\n{}", "Allocations:".bright_green())?;
+    for allocation in &details.allocations {
+        writeln!(
+            out,
+            "  {} @ {} {}",
+            allocation.amount.to_string().bright_yellow(),
+            allocation.outpoint,
+            format!("({})", allocation.node_outpoint).dimmed()
+        )?;
+    }
+
+    if !details.inflation.is_empty() {
+        writeln!(out, "\n{}", "Inflation rights:".bright_green())?;
+    }
+    for right in &details.inflation {
+        writeln!(
+            out,
+            "  {} @ {} {}",
+            right.amount.to_string().bright_yellow(),
+            right.outpoint,
+            format!("({})", right.node_outpoint).dimmed()
+        )?;
+    }
+
+    writeln!(out, "\n{}", "Attachments:".bright_green())?;
+    for attachment in &details.attachments {
+        writeln!(
+            out,
+            "  {} ({}, salt {}) @ {}",
+            attachment.id.to_string().bright_yellow(),
+            attachment.mime,
+            attachment.salt,
+            attachment.outpoint
+        )?;
+    }
+
+    writeln!(out, "\n{}", "History:".bright_green())?;
+    for transition in &details.history {
+        writeln!(
+            out,
+            "  {} {} {} {}",
+            transition.transition_type.bright_yellow(),
+            transition.node_id,
+            "witness".dimmed(),
+            transition.witness_txid
+        )?;
+        for input in &transition.inputs {
+            writeln!(out, "    {} {}", "closes".dimmed(), input)?;
+        }
+        writeln!(
+            out,
+            "    {} {} rights",
+            "assigns".dimmed(),
+            transition.outputs
+        )?;
+    }
+
+    writeln!(out, "\n{}", "Endpoints:".bright_green())?;
+    for endpoint in &details.endpoints {
+        writeln!(
+            out,
+            "  {} {} {}",
+            endpoint.seal.to_string().bright_yellow(),
+            "from bundle".dimmed(),
+            endpoint.bundle_id
+        )?;
+    }
+    Ok(())
+}
+
+fn print_provenance(out: &mut impl Write, provenance: &Provenance) -> io::Result<()> {
+    writeln!(
+        out,
+        "{} {}",
+        "Contract ID:".bright_green(),
+        provenance.contract_id.to_string().bright_yellow()
+    )?;
+    for record in &provenance.records {
+        write!(
+            out,
+            "\n{} {}",
+            record.node_type.bright_yellow(),
+            record.node_id
+        )?;
+        match record.witness_txid {
+            Some(txid) => writeln!(out, " {} {}", "witness".dimmed(), txid)?,
+            None => writeln!(out)?,
+        }
+        for input in &record.inputs {
+            writeln!(out, "    {} {}", "closes".dimmed(), input)?;
+        }
+        let seal = |seal: Option<OutPoint>| {
+            seal.map(|seal| seal.to_string())
+                .unwrap_or_else(|| s!("concealed seal"))
+        };
+        for output in &record.outputs {
+            let amount = output
+                .amount
+                .map(|amount| amount.to_string())
+                .unwrap_or_else(|| s!("confidential amount"));
+            writeln!(
+                out,
+                "    {} {} @ {} {}",
+                "assigns".dimmed(),
+                amount.bright_yellow(),
+                seal(output.seal),
+                format!("({})", output.node_outpoint).dimmed()
+            )?;
+        }
+        for engraving in &record.engravings {
+            let attachment = match (engraving.id, &engraving.mime) {
+                (Some(id), Some(mime)) => format!("{} ({})", id, mime),
+                _ => s!("concealed attachment"),
+            };
+            writeln!(
+                out,
+                "    {} {} @ {} {}",
+                "engraves".dimmed(),
+                attachment.bright_yellow(),
+                seal(engraving.seal),
+                format!("({})", engraving.node_outpoint).dimmed()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_invoice(out: &mut impl Write, invoice: &Invoice) -> io::Result<()> {
+    writeln!(
+        out,
+        "{} {}",
+        "Contract ID:".bright_green(),
+        invoice.contract_id.to_string().bright_yellow()
+    )?;
+    match invoice.request {
+        InvoiceRequest::Amount(amount) => writeln!(out, "{} {}", "Amount:".bright_green(), amount)?,
+        InvoiceRequest::Token(token) => writeln!(out, "{} {}", "Token:".bright_green(), token)?,
+    }
+    writeln!(out, "{} {}", "Seal:".bright_green(), invoice.seal)?;
+    writeln!(out, "{} {}", "Network:".bright_green(), invoice.network)?;
+    if let Some(expiry) = invoice.expiry {
+        let status = if invoice.is_expired() { "expired".bright_red() } else { "valid".normal() };
+        writeln!(out, "{} {} ({})", "Expiry:".bright_green(), expiry, status)?;
+    }
+    if let Some(attachment) = invoice.attachment {
+        writeln!(out, "{} {}", "Attachment:".bright_green(), attachment)?;
+    }
+    if let Some(mime) = &invoice.mime {
+        writeln!(out, "{} {}", "MIME type:".bright_green(), mime)?;
+    }
+    Ok(())
+}
+
+/// Saves strict-encoded state transition to a file, printing its YAML
+/// representation
+fn save_transition(transition: &Transition, output: PathBuf) -> Result<(), Error> {
+    transition.strict_file_save(output)?;
+
+    println!("{}", serde_yaml::to_string(transition)?);
+    println!("{}", "Success".bold().bright_green());
+    Ok(())
+}
+
+/// Loads consignment from a file in any of the supported formats. If the
+/// stash is used and the argument is a contract id, the consignment is
+/// restored from the stash instead.
+fn load_consignment(stash: Option<&Stash>, source: &Path) -> Result<StateTransfer, Error> {
+    let contract_id = source.to_str().and_then(|s| ContractId::from_str(s).ok());
+    match (stash, contract_id) {
+        (Some(stash), Some(contract_id)) => Ok(stash.consignment(contract_id)?),
+        _ => Ok(import_consignment(&fs::read(source)?, None)?),
+    }
+}
+
+/// Returns stash, failing if `--stash` option was not provided
+fn require_stash(stash: Option<&Stash>) -> Result<&Stash, Error> {
+    stash.ok_or_else(|| Error::Usage(s!("the command requires --stash option")))
+}
+
+/// Imports consignment into the stash, if it is used, validating it with the
+/// witness transactions from the `resolver` and the ones known to the stash
+fn stash_consignment<T>(
+    stash: Option<&Stash>,
+    consignment: &InmemConsignment<T>,
+    resolver: &MemTxResolver,
+) -> Result<(), Error>
+where
+    T: ConsignmentType,
+{
+    if let Some(stash) = stash {
+        let added = stash.import(consignment, resolver)?;
+        eprintln!(
+            "{} {} {}",
+            "Stashed".bright_green(),
+            added.to_string().bright_yellow(),
+            "new nodes".bright_green()
+        );
+    }
+    Ok(())
+}
+
+/// Lists seals revealed in the source consignment and the outgoing bundle
+fn known_seals<T>(source: &InmemConsignment<T>, bundle: &TransitionBundle) -> Vec<seal::Revealed>
+where T: ConsignmentType {
+    source
+        .anchored_bundles()
+        .flat_map(|(_, bundle)| bundle.revealed_iter())
+        .chain(bundle.revealed_iter())
+        .map(|(transition, _)| transition as &dyn Node)
+        .chain([source.genesis() as &dyn Node])
+        .flat_map(|node| {
+            node.owned_rights()
+                .iter()
+                .flat_map(|(_, assignments)| assignments.filter_revealed_seals())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Stores the consignment composed for the beneficiary in the stash,
+/// revealing the sender seals concealed in it, such that the stash keeps
+/// track of the change
+fn stash_transfer(
+    stash: Option<&Stash>,
+    transfer: &StateTransfer,
+    seals: &[seal::Revealed],
+    resolver: &MemTxResolver,
+) -> Result<(), Error> {
+    let mut transfer = transfer.clone();
+    transfer.reveal_seals(seals);
+    stash_consignment(stash, &transfer, resolver)
+}
+
+/// Reads bitcoin transaction from file, which may contain either its binary
+/// consensus encoding or the hex encoding of it
+fn load_tx(path: &Path) -> Result<Transaction, Error> {
+    let data = fs::read(path)?;
+    if let Ok(tx) = bitcoin::consensus::deserialize(&data) {
+        return Ok(tx);
+    }
+    String::from_utf8(data)
+        .ok()
+        .and_then(|s| Vec::<u8>::from_hex(s.trim()).ok())
+        .and_then(|data| bitcoin::consensus::deserialize(&data).ok())
+        .ok_or_else(|| Error::Usage(format!("{} is not a bitcoin transaction", path.display())))
+}
+
+/// Reads PSBT from file, which may contain either binary PSBT data or their
+/// Base64 encoding
+fn load_psbt(path: &Path) -> Result<PartiallySignedTransaction, Error> {
+    let data = fs::read(path)?;
+    if data.starts_with(b"psbt\xff") {
+        bitcoin::consensus::deserialize(&data).map_err(|err| Error::InvalidPsbt(err.to_string()))
+    } else {
+        let s = String::from_utf8(data).map_err(|err| Error::InvalidPsbt(err.to_string()))?;
+        PartiallySignedTransaction::from_str(s.trim())
+            .map_err(|err| Error::InvalidPsbt(err.to_string()))
+    }
+}
+
+/// Parses file attachment in form of `<path>[:<mime>]`
+fn file_attachment(arg: &str, salt: Option<u64>) -> Result<FileAttachment, Error> {
+    let (path, mime) = match arg.rsplit_once(':') {
+        Some((path, mime)) if mime.contains('/') => (path, Some(mime)),
+        _ => (arg, None),
+    };
+    let file_path = PathBuf::from(path);
+    if !file_path.is_file() {
+        return Err(Error::Usage(format!(
+            "attachment file '{}' does not exist",
+            path
+        )));
+    }
+    let mime = mime.unwrap_or_else(|| mime_type(&file_path));
+    let mime = AsciiString::from_str(mime).map_err(|_| {
+        Error::Usage(format!(
+            "MIME type '{}' must contain only ASCII characters",
+            mime
+        ))
+    })?;
+    Ok(FileAttachment {
+        file_path,
+        mime,
+        salt: salt.unwrap_or_else(|| thread_rng().gen()),
+    })
+}
+
+/// Detects MIME type of a file from its extension
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_lowercase);
+    match ext.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        Some("pdf") => "application/pdf",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("html" | "htm") => "text/html",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Reads data blob either from a file or from a hex string
+fn data_blob(arg: &str) -> Result<Vec<u8>, Error> {
+    if Path::new(arg).is_file() {
+        return Ok(fs::read(arg)?);
+    }
+    Vec::<u8>::from_hex(arg).map_err(|_| {
+        Error::Usage(format!(
+            "data '{}' is neither an existing file nor a hex string",
+            arg
+        ))
+    })
+}
+
+/// Parses Ricardian contract given either as a path to the contract text file
+/// or in the form of the genesis field value committing to the text hash
+fn ricardian_contract(arg: &str) -> Result<RicardianContract, Error> {
+    if Path::new(arg).is_file() {
+        let text = fs::read_to_string(arg)?;
+        return RicardianContract::with_text(text).map_err(|err| Error::Usage(err.to_string()));
+    }
+    match RicardianContract::from_str(arg) {
+        Ok(contract @ RicardianContract::Hash { .. }) => Ok(contract),
+        _ => Err(Error::Usage(format!(
+            "contract '{}' is neither an existing file nor a hash of the contract text",
+            arg
+        ))),
+    }
+}
+
 fn parent_validator(parent_id: &str) -> Result<(), String> {
     let contract = ContractId::from_str(parent_id);
 
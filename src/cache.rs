@@ -0,0 +1,499 @@
+//! SQLite-backed cache of RGB121 asset allocations for wallets.
+//!
+//! The cache keeps asset metadata together with the allocations of the asset
+//! amounts and attachments. Allocations are never deleted: when a state
+//! transition is accepted or an asset is stored, allocations spent by it are
+//! marked as spent and the new ones are added, so the cache can answer queries
+//! about both the current and the historical state.
+//!
+//! Asset amounts are stored as SQLite 64-bit signed integers using bitwise
+//! conversion; sums are always computed on the Rust side.
+
+use std::borrow::Borrow;
+use std::path::Path;
+use std::str::FromStr;
+
+use amplify::{Slice32, Wrapper};
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, Txid};
+use rgb::contract::attachment::{self, AttachmentId};
+use rgb::value::BlindingFactor;
+use rgb::{
+    value, AtomicValue, ContractId, Node, NodeId, NodeOutpoint, OwnedAttachment, OwnedValue,
+    Transition, TypedAssignments,
+};
+use rusqlite::{params, Connection, Row};
+use stens::AsciiString;
+
+use crate::transitions::checked_sum;
+use crate::{Asset, OwnedRightType};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS assets (
+        contract_id BLOB PRIMARY KEY,
+        schema_id TEXT NOT NULL,
+        name TEXT,
+        description TEXT,
+        precision INTEGER,
+        issued_supply INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS allocations (
+        contract_id BLOB NOT NULL,
+        node_id BLOB NOT NULL,
+        ty INTEGER NOT NULL,
+        no INTEGER NOT NULL,
+        txid BLOB NOT NULL,
+        vout INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        blinding BLOB NOT NULL,
+        spent INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (contract_id, node_id, ty, no)
+    );
+    CREATE INDEX IF NOT EXISTS allocations_outpoint ON allocations (txid, vout);
+    CREATE TABLE IF NOT EXISTS attachments (
+        contract_id BLOB NOT NULL,
+        node_id BLOB NOT NULL,
+        ty INTEGER NOT NULL,
+        no INTEGER NOT NULL,
+        txid BLOB NOT NULL,
+        vout INTEGER NOT NULL,
+        attachment_id BLOB NOT NULL,
+        mime TEXT NOT NULL,
+        salt INTEGER NOT NULL,
+        spent INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (contract_id, node_id, ty, no)
+    );
+    CREATE INDEX IF NOT EXISTS attachments_outpoint ON attachments (txid, vout);
+";
+
+/// Errors happening during asset cache operations
+#[derive(Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// SQLite failure: {0}
+    #[from]
+    Sqlite(rusqlite::Error),
+
+    /// cache contains invalid data in column {0}
+    InvalidData(&'static str),
+
+    /// contract {0} is not present in the cache
+    UnknownContract(ContractId),
+
+    /// sum of the allocated amounts exceeds the maximal asset amount
+    Overflow,
+}
+
+/// Cached asset allocation, which may be already spent
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CachedAllocation {
+    /// Contract of the allocated asset
+    pub contract_id: ContractId,
+    /// Allocation data
+    pub allocation: OwnedValue,
+    /// Whether the allocation was spent by one of the accepted transitions
+    pub spent: bool,
+}
+
+/// Cached attachment (engraving right), which may be already spent
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CachedAttachment {
+    /// Contract of the asset
+    pub contract_id: ContractId,
+    /// Attachment data
+    pub attachment: OwnedAttachment,
+    /// Whether the attachment was spent by one of the accepted transitions
+    pub spent: bool,
+}
+
+/// SQLite-backed cache of RGB121 assets and their allocations
+#[derive(Debug)]
+pub struct AssetCache {
+    connection: Connection,
+}
+
+impl AssetCache {
+    /// Opens cache in the database file, creating the file and the tables if
+    /// they do not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<AssetCache, Error> {
+        AssetCache::with(Connection::open(path)?)
+    }
+
+    /// Creates cache kept in memory, mostly useful for testing
+    pub fn in_memory() -> Result<AssetCache, Error> {
+        AssetCache::with(Connection::open_in_memory()?)
+    }
+
+    fn with(connection: Connection) -> Result<AssetCache, Error> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(AssetCache { connection })
+    }
+
+    /// Stores the asset and its allocations, replacing the cached asset
+    /// metadata. Allocations cached before are kept; the ones spent according
+    /// to the asset are marked as spent.
+    pub fn store_asset(&mut self, asset: &Asset) -> Result<(), Error> {
+        let contract_id = asset.contract_id();
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO assets
+                (contract_id, schema_id, name, description, precision, issued_supply)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id_blob(contract_id),
+                asset.schema_id().to_string(),
+                asset.name().map(|name| name.to_string()),
+                asset
+                    .description()
+                    .map(|description| description.to_string()),
+                asset.precision(),
+                to_sql_amount(asset.issued_supply().map_err(|_| Error::Overflow)?),
+            ],
+        )?;
+        for coin in asset.known_coins() {
+            insert_allocation(&tx, contract_id, coin, false)?;
+        }
+        for coin in asset.spent_coins() {
+            insert_allocation(&tx, contract_id, coin, true)?;
+        }
+        for attachment in asset.known_attachments() {
+            insert_attachment(&tx, contract_id, attachment)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Lists ids of all cached contracts
+    pub fn contract_ids(&self) -> Result<Vec<ContractId>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT contract_id FROM assets ORDER BY contract_id")?;
+        let rows = statement.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+        rows.map(|blob| Ok(ContractId::from_inner(hash(blob?, "contract_id")?)))
+            .collect()
+    }
+
+    /// Updates the cache with the state transition accepted by the wallet,
+    /// marking allocations spent by the transition and adding allocations
+    /// with revealed seals it creates. Seals without transaction id are
+    /// resolved to the outputs of the `witness_txid`.
+    pub fn accept_transition(
+        &mut self,
+        contract_id: ContractId,
+        witness_txid: Txid,
+        transition: &Transition,
+    ) -> Result<(), Error> {
+        if !self.contract_ids()?.contains(&contract_id) {
+            return Err(Error::UnknownContract(contract_id));
+        }
+        let node_id = transition.node_id();
+        let tx = self.connection.transaction()?;
+        for output in transition.parent_outputs() {
+            for table in ["allocations", "attachments"] {
+                tx.execute(
+                    &format!(
+                        "UPDATE {} SET spent = 1
+                            WHERE contract_id = ?1 AND node_id = ?2 AND ty = ?3 AND no = ?4",
+                        table
+                    ),
+                    params![id_blob(contract_id), id_blob(output.node_id), output.ty, output.no],
+                )?;
+            }
+        }
+        for (ty, assignments) in transition.owned_rights().iter() {
+            for (no, assignment) in assignments_iter(assignments) {
+                match assignment {
+                    // Inflation rights are not asset allocations
+                    Revealed::Value(..) if *ty != OwnedRightType::Assets as u16 => {}
+                    Revealed::Value(seal, state) => insert_allocation(
+                        &tx,
+                        contract_id,
+                        &OwnedValue::with(seal, witness_txid, state, node_id, *ty, no),
+                        false,
+                    )?,
+                    Revealed::Attachment(seal, state) => insert_attachment(
+                        &tx,
+                        contract_id,
+                        &OwnedAttachment::with(seal, witness_txid, state, node_id, *ty, no),
+                    )?,
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Lists all allocations of all contracts on the transaction output,
+    /// including the spent ones
+    pub fn outpoint_allocations(&self, outpoint: OutPoint) -> Result<Vec<CachedAllocation>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT contract_id, node_id, ty, no, txid, vout, amount, blinding, spent
+                FROM allocations
+                WHERE txid = ?1 AND vout = ?2 ORDER BY contract_id, node_id, ty, no",
+        )?;
+        let rows = statement.query_map(params![id_blob(outpoint.txid), outpoint.vout], |row| {
+            Ok(allocation_row(row))
+        })?;
+        rows.map(|row| row?).collect()
+    }
+
+    /// Lists all attachments (engraving rights) of all contracts on the
+    /// transaction output, including the spent ones
+    pub fn outpoint_attachments(&self, outpoint: OutPoint) -> Result<Vec<CachedAttachment>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT contract_id, node_id, ty, no, txid, vout, attachment_id, mime, salt, spent
+                FROM attachments WHERE txid = ?1 AND vout = ?2
+                ORDER BY contract_id, node_id, ty, no",
+        )?;
+        let rows = statement.query_map(params![id_blob(outpoint.txid), outpoint.vout], |row| {
+            Ok(attachment_row(row))
+        })?;
+        rows.map(|row| row?).collect()
+    }
+
+    /// Lists unspent allocations of the contract
+    pub fn unspent_allocations(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Vec<CachedAllocation>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT contract_id, node_id, ty, no, txid, vout, amount, blinding, spent
+                FROM allocations
+                WHERE contract_id = ?1 AND spent = 0 ORDER BY txid, vout, node_id, ty, no",
+        )?;
+        let rows =
+            statement.query_map(params![id_blob(contract_id)], |row| Ok(allocation_row(row)))?;
+        rows.map(|row| row?).collect()
+    }
+
+    /// Computes balance of the contract as a sum of all its unspent
+    /// allocations
+    pub fn balance(&self, contract_id: ContractId) -> Result<AtomicValue, Error> {
+        if !self.contract_ids()?.contains(&contract_id) {
+            return Err(Error::UnknownContract(contract_id));
+        }
+        checked_sum(
+            self.unspent_allocations(contract_id)?
+                .into_iter()
+                .map(|cached| cached.allocation.state.value),
+        )
+        .ok_or(Error::Overflow)
+    }
+}
+
+/// Revealed assignment of one of the state types cached by [`AssetCache`]
+enum Revealed {
+    Value(rgb::seal::Revealed, value::Revealed),
+    Attachment(rgb::seal::Revealed, attachment::Revealed),
+}
+
+/// Iterates over revealed asset and attachment assignments, together with
+/// their numbers
+fn assignments_iter(assignments: &TypedAssignments) -> Vec<(u16, Revealed)> {
+    match assignments {
+        TypedAssignments::Value(assignments) => assignments
+            .iter()
+            .enumerate()
+            .filter_map(|(no, a)| {
+                a.to_revealed()
+                    .map(|(seal, state)| (no as u16, Revealed::Value(seal, state)))
+            })
+            .collect(),
+        TypedAssignments::Attachment(assignments) => assignments
+            .iter()
+            .enumerate()
+            .filter_map(|(no, a)| {
+                a.to_revealed()
+                    .map(|(seal, state)| (no as u16, Revealed::Attachment(seal, state)))
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Adds allocation to the cache; allocations which are already cached may
+/// only become spent
+fn insert_allocation(
+    connection: &Connection,
+    contract_id: ContractId,
+    coin: &OwnedValue,
+    spent: bool,
+) -> Result<(), Error> {
+    connection.execute(
+        "INSERT INTO allocations
+            (contract_id, node_id, ty, no, txid, vout, amount, blinding, spent)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (contract_id, node_id, ty, no)
+            DO UPDATE SET spent = max(spent, excluded.spent)",
+        params![
+            id_blob(contract_id),
+            id_blob(coin.outpoint.node_id),
+            coin.outpoint.ty,
+            coin.outpoint.no,
+            id_blob(coin.seal.txid),
+            coin.seal.vout,
+            to_sql_amount(coin.state.value),
+            AsRef::<[u8]>::as_ref(&coin.state.blinding).to_vec(),
+            spent,
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_attachment(
+    connection: &Connection,
+    contract_id: ContractId,
+    attachment: &OwnedAttachment,
+) -> Result<(), Error> {
+    connection.execute(
+        "INSERT OR IGNORE INTO attachments
+            (contract_id, node_id, ty, no, txid, vout, attachment_id, mime, salt)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id_blob(contract_id),
+            id_blob(attachment.outpoint.node_id),
+            attachment.outpoint.ty,
+            attachment.outpoint.no,
+            id_blob(attachment.seal.txid),
+            attachment.seal.vout,
+            id_blob(attachment.state.id),
+            attachment.state.mime.to_string(),
+            to_sql_amount(attachment.state.salt),
+        ],
+    )?;
+    Ok(())
+}
+
+fn allocation_row(row: &Row) -> Result<CachedAllocation, Error> {
+    Ok(CachedAllocation {
+        contract_id: ContractId::from_inner(hash(row.get(0)?, "contract_id")?),
+        allocation: OwnedValue {
+            outpoint: node_outpoint(row)?,
+            seal: OutPoint::new(hash::<Txid>(row.get(4)?, "txid")?, row.get(5)?),
+            state: value::Revealed {
+                value: from_sql_amount(row.get(6)?),
+                blinding: Slice32::from_slice(row.get::<_, Vec<u8>>(7)?)
+                    .map(BlindingFactor::from_inner)
+                    .ok_or(Error::InvalidData("blinding"))?,
+            },
+        },
+        spent: row.get(8)?,
+    })
+}
+
+fn attachment_row(row: &Row) -> Result<CachedAttachment, Error> {
+    let mime = row.get::<_, String>(7)?;
+    Ok(CachedAttachment {
+        contract_id: ContractId::from_inner(hash(row.get(0)?, "contract_id")?),
+        attachment: OwnedAttachment {
+            outpoint: node_outpoint(row)?,
+            seal: OutPoint::new(hash::<Txid>(row.get(4)?, "txid")?, row.get(5)?),
+            state: attachment::Revealed {
+                id: AttachmentId::from_inner(hash(row.get(6)?, "attachment_id")?),
+                mime: AsciiString::from_str(&mime).map_err(|_| Error::InvalidData("mime"))?,
+                salt: from_sql_amount(row.get(8)?),
+            },
+        },
+        spent: row.get(9)?,
+    })
+}
+
+fn node_outpoint(row: &Row) -> Result<NodeOutpoint, Error> {
+    Ok(NodeOutpoint::new(
+        NodeId::from_inner(hash(row.get(1)?, "node_id")?),
+        row.get(2)?,
+        row.get(3)?,
+    ))
+}
+
+fn id_blob<H>(id: H) -> Vec<u8>
+where H: Borrow<[u8]> {
+    id.borrow().to_vec()
+}
+
+fn hash<H>(blob: Vec<u8>, column: &'static str) -> Result<H, Error>
+where H: Hash {
+    H::from_slice(&blob).map_err(|_| Error::InvalidData(column))
+}
+
+fn to_sql_amount(amount: u64) -> i64 { amount as i64 }
+
+fn from_sql_amount(amount: i64) -> u64 { amount as u64 }
+
+#[cfg(test)]
+mod test {
+    use rgb::seal;
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::test_helpers::{beneficiary, contract, outpoint};
+
+    const WITNESS_TXID: &str = "0c05cea88d0fca7d16ed6a26d622c7ea477f3fc6e0b3b0ea7d3db1e89ce35a48";
+
+    #[test]
+    fn cache() {
+        let contract = contract(&[(0, 5), (1, u64::MAX - 5)]);
+        let asset = Asset::try_from(&contract).unwrap();
+        let contract_id = asset.contract_id();
+
+        let mut cache = AssetCache::in_memory().unwrap();
+        cache.store_asset(&asset).unwrap();
+        assert_eq!(cache.contract_ids().unwrap(), vec![contract_id]);
+        assert_eq!(cache.balance(contract_id).unwrap(), u64::MAX);
+        let allocations = cache.outpoint_allocations(outpoint(1)).unwrap();
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(
+            Some(&allocations[0].allocation),
+            asset.known_coins().find(|coin| coin.seal == outpoint(1))
+        );
+
+        let beneficiary = beneficiary(2);
+        let change = seal::Revealed {
+            method: CloseMethod::OpretFirst,
+            txid: None,
+            vout: 0,
+            blinding: 1,
+        };
+        let transition = asset
+            .transfer(
+                bset![outpoint(0)],
+                bmap! { beneficiary => 2 },
+                bmap! { change => 3 },
+            )
+            .unwrap();
+        let witness_txid = Txid::from_str(WITNESS_TXID).unwrap();
+        cache
+            .accept_transition(contract_id, witness_txid, &transition)
+            .unwrap();
+        // Re-accepting the same transition does not change the state
+        cache
+            .accept_transition(contract_id, witness_txid, &transition)
+            .unwrap();
+
+        assert_eq!(cache.balance(contract_id).unwrap(), u64::MAX - 2);
+        assert!(cache.outpoint_allocations(outpoint(0)).unwrap()[0].spent);
+        let change = cache
+            .outpoint_allocations(OutPoint::new(witness_txid, 0))
+            .unwrap();
+        assert_eq!(change.len(), 1);
+        assert_eq!(change[0].allocation.state.value, 3);
+        assert!(!change[0].spent);
+        assert_eq!(cache.unspent_allocations(contract_id).unwrap().len(), 2);
+
+        // Storing the asset again keeps the history of accepted transitions
+        cache.store_asset(&asset).unwrap();
+        assert!(cache.outpoint_allocations(outpoint(0)).unwrap()[0].spent);
+        assert_eq!(cache.balance(contract_id).unwrap(), u64::MAX - 2);
+    }
+
+    #[test]
+    fn unknown_contract() {
+        let asset = Asset::try_from(&contract(&[(0, 5)])).unwrap();
+        let cache = AssetCache::in_memory().unwrap();
+        assert!(matches!(
+            cache.balance(asset.contract_id()),
+            Err(Error::UnknownContract(id)) if id == asset.contract_id()
+        ));
+        assert!(cache.outpoint_attachments(outpoint(0)).unwrap().is_empty());
+    }
+}
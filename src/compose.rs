@@ -0,0 +1,374 @@
+//! Composition of outgoing state transfer consignments, which provide the
+//! beneficiary with the new state transition and the minimal history required
+//! to validate it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use commit_verify::{lnpbp4, CommitConceal};
+use rgb::{
+    seal, Anchor, AnchoredBundles, AttachmentId, ConcealSeals, Consignment, ConsignmentEndseals,
+    ConsignmentType, ExtensionList, Genesis, InmemConsignment, Node, NodeId, NodeOutpoint, Schema,
+    SealEndpoint, StateTransfer, Transition, TransitionBundle,
+};
+use strict_encoding::{strict_deserialize, strict_serialize, LargeVec};
+
+/// Errors happening during composition of state transfer consignment
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// transition bundle does not contain any revealed state transitions
+    EmptyBundle,
+
+    /// history of node {0} is absent from the source consignment
+    MissingHistory(NodeId),
+
+    /// unable to encode consignment: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Composes state transfer consignment for the beneficiary of the state
+/// transitions from the `bundle`, which is committed to the witness
+/// transaction with the `anchor`.
+///
+/// Only the part of the `source` consignment history which is required to
+/// validate the inputs of the bundle transitions is included. The consignment
+/// endpoints are the bundle outputs assigned to the concealed seals or to the
+/// witness transaction outputs, i.e. all outputs except the ones assigned to
+/// the explicit (usually change) seals.
+///
+/// Seals of the state transitions which are neither the endpoints nor closed
+/// by the consignment transitions are concealed, such that the beneficiary
+/// does not learn the change outputs. The sender may restore them with
+/// [`rgb::RevealSeals`] to keep track of the change. Data containers of the
+/// `source` for the attachments assigned by the consignment nodes are
+/// included.
+pub fn compose_transfer<T>(
+    source: &InmemConsignment<T>,
+    anchor: Anchor<lnpbp4::MerkleProof>,
+    bundle: TransitionBundle,
+) -> Result<StateTransfer, Error>
+where
+    T: ConsignmentType,
+{
+    let transitions: Vec<&Transition> = bundle.revealed_iter().map(|(t, _)| t).collect();
+    if transitions.is_empty() {
+        return Err(Error::EmptyBundle);
+    }
+
+    let history = history(source, &transitions)?;
+    let mut anchored_bundles = source
+        .anchored_bundles()
+        .enumerate()
+        .filter(|(index, _)| history.contains(index))
+        .map(|(_, anchored_bundle)| anchored_bundle.clone())
+        .collect::<Vec<_>>();
+
+    let bundle_id = bundle.bundle_id();
+    let endpoints = transitions
+        .iter()
+        .flat_map(|transition| endpoints(transition))
+        .collect::<BTreeSet<_>>();
+    let exposed = endpoints
+        .iter()
+        .map(SealEndpoint::commit_conceal)
+        .collect::<BTreeSet<_>>();
+    let spent = anchored_bundles
+        .iter()
+        .flat_map(|(_, bundle)| bundle.revealed_iter().map(|(t, _)| t))
+        .chain(transitions)
+        .flat_map(|transition| {
+            transition
+                .parent_owned_rights()
+                .iter()
+                .flat_map(|(node_id, rights)| {
+                    rights.iter().flat_map(move |(ty, indexes)| {
+                        indexes
+                            .iter()
+                            .map(move |no| NodeOutpoint::new(*node_id, *ty, *no))
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+
+    anchored_bundles.push((anchor, bundle));
+    for (_, bundle) in &mut anchored_bundles {
+        let seals = concealed_seals(
+            bundle.revealed_iter().map(|(t, _)| t as &dyn Node),
+            &spent,
+            &exposed,
+        );
+        bundle.conceal_seals(&seals);
+    }
+
+    let attachments = anchored_bundles
+        .iter()
+        .flat_map(|(_, bundle)| bundle.revealed_iter().map(|(t, _)| t as &dyn Node))
+        .chain([source.genesis() as &dyn Node])
+        .flat_map(|node| {
+            node.owned_rights()
+                .iter()
+                .flat_map(|(_, assignments)| assignments.filter_revealed_state_attachments())
+                .map(|attachment| attachment.id)
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+    let data_containers = data_containers(source)?
+        .into_iter()
+        .filter(|(id, _)| attachments.contains(id))
+        .collect();
+
+    let extensions = source.state_extensions().cloned().collect::<Vec<_>>();
+    let consignment = ConsignmentData {
+        version: source.version(),
+        schema: source.schema().clone(),
+        root_schema: source.root_schema().cloned(),
+        genesis: source.genesis().clone(),
+        endseals: endpoints
+            .into_iter()
+            .map(|endpoint| (bundle_id, endpoint))
+            .collect(),
+        anchored_bundles: anchored_bundles.try_into()?,
+        state_extensions: extensions.try_into()?,
+        data_containers,
+    };
+    let consignment = strict_deserialize(strict_serialize(&consignment)?)?;
+
+    Ok(consignment)
+}
+
+/// Collects indexes of the source consignment anchored bundles, which contain
+/// ancestors of the provided transitions
+fn history<T>(
+    source: &InmemConsignment<T>,
+    transitions: &[&Transition],
+) -> Result<BTreeSet<usize>, Error>
+where
+    T: ConsignmentType,
+{
+    let bundle_index: BTreeMap<NodeId, usize> = source
+        .anchored_bundles()
+        .enumerate()
+        .flat_map(|(index, (_, bundle))| {
+            bundle
+                .revealed_iter()
+                .map(move |(transition, _)| (transition.node_id(), index))
+        })
+        .collect();
+    let genesis_id = source.genesis().node_id();
+
+    let mut history = bset![];
+    let mut queue: Vec<NodeId> = transitions.iter().flat_map(|t| parents(t)).collect();
+    while let Some(node_id) = queue.pop() {
+        if node_id == genesis_id {
+            continue;
+        }
+        let index = *bundle_index
+            .get(&node_id)
+            .ok_or(Error::MissingHistory(node_id))?;
+        if !history.insert(index) {
+            continue;
+        }
+        let (_, bundle) = source
+            .anchored_bundles()
+            .nth(index)
+            .expect("bundle index is built from the same consignment");
+        queue.extend(bundle.revealed_iter().flat_map(|(t, _)| parents(t)));
+    }
+    Ok(history)
+}
+
+/// Strict encoding of the consignment, i.e. the format of consignment files.
+/// RGB Standard Library does not provide API to access data containers of the
+/// consignment, thus consignments are re-encoded to access them.
+#[derive(StrictEncode, StrictDecode)]
+struct ConsignmentData {
+    version: u8,
+    schema: Schema,
+    root_schema: Option<Schema>,
+    genesis: Genesis,
+    endseals: ConsignmentEndseals,
+    anchored_bundles: AnchoredBundles,
+    state_extensions: ExtensionList,
+    data_containers: BTreeMap<AttachmentId, LargeVec<u8>>,
+}
+
+/// Extracts data containers (attachment data) from the consignment
+fn data_containers<T>(
+    consignment: &InmemConsignment<T>,
+) -> Result<BTreeMap<AttachmentId, LargeVec<u8>>, Error>
+where T: ConsignmentType {
+    let data: ConsignmentData = strict_deserialize(strict_serialize(consignment)?)?;
+    Ok(data.data_containers)
+}
+
+/// Lists seals of the node outputs, which are neither closed by the
+/// consignment transitions nor exposed as consignment endpoints
+fn concealed_seals<'node>(
+    nodes: impl IntoIterator<Item = &'node dyn Node>,
+    spent: &BTreeSet<NodeOutpoint>,
+    exposed: &BTreeSet<seal::Confidential>,
+) -> Vec<seal::Confidential> {
+    let mut keep = bset![];
+    let mut conceal = bset![];
+    for node in nodes {
+        let node_id = node.node_id();
+        for (ty, assignments) in node.owned_rights().iter() {
+            for (seal, no) in assignments.revealed_seal_outputs() {
+                let seal = seal.commit_conceal();
+                if exposed.contains(&seal) || spent.contains(&NodeOutpoint::new(node_id, *ty, no)) {
+                    keep.insert(seal);
+                } else {
+                    conceal.insert(seal);
+                }
+            }
+        }
+    }
+    conceal.difference(&keep).copied().collect()
+}
+
+fn parents(transition: &Transition) -> Vec<NodeId> {
+    transition.parent_owned_rights().keys().copied().collect()
+}
+
+/// Lists transition outputs which are assigned to the concealed seals or to
+/// the witness transaction outputs
+fn endpoints(transition: &Transition) -> Vec<SealEndpoint> {
+    let mut endpoints = vec![];
+    for (_, assignments) in transition.owned_rights().iter() {
+        let revealed = assignments.filter_revealed_seals();
+        let revealed_concealed = revealed
+            .iter()
+            .map(CommitConceal::commit_conceal)
+            .collect::<BTreeSet<_>>();
+        endpoints.extend(
+            revealed
+                .into_iter()
+                .filter(|seal| seal.txid.is_none())
+                .map(SealEndpoint::from),
+        );
+        endpoints.extend(
+            assignments
+                .to_confidential_seals()
+                .into_iter()
+                .filter(|seal| !revealed_concealed.contains(seal))
+                .map(SealEndpoint::ConcealedUtxo),
+        );
+    }
+    endpoints
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256, Hash};
+    use commit_verify::CommitVerify;
+    use rgb::{Contract, Validity};
+
+    use super::*;
+    use crate::test_helpers::{anchor, contract, outpoint, seal, Issuance, TempFile};
+    use crate::{Asset, MemTxResolver, ValidationReport};
+
+    #[test]
+    fn minimal_history() {
+        let contract = contract(&[(0, 5), (1, 5)]);
+        let asset = Asset::try_from(&contract).unwrap();
+
+        let transition = asset
+            .transfer(bset![outpoint(0)], bmap! {}, bmap! { seal(2) => 5 })
+            .unwrap();
+        let node_a = transition.node_id();
+        let (anchor_a, bundle_a, tx_a) = anchor(&contract, transition, outpoint(0));
+        let transition = asset
+            .transfer(bset![outpoint(1)], bmap! {}, bmap! { seal(3) => 5 })
+            .unwrap();
+        let (anchor_b, bundle_b, tx_b) = anchor(&contract, transition, outpoint(1));
+
+        let mut source = StateTransfer::with(
+            contract.schema().clone(),
+            None,
+            contract.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        source
+            .push_anchored_bundle(anchor_a, bundle_a.clone())
+            .unwrap();
+        source
+            .push_anchored_bundle(anchor_b, bundle_b.clone())
+            .unwrap();
+
+        let beneficiary = SealEndpoint::ConcealedUtxo(seal(4).commit_conceal());
+        let transition = Asset::try_from(&source)
+            .unwrap()
+            .transfer(bset![outpoint(2)], bmap! { beneficiary => 5 }, bmap! {})
+            .unwrap();
+        let (anchor_c, bundle_c, tx_c) = anchor(&contract, transition, outpoint(2));
+
+        let consignment = compose_transfer(&source, anchor_c.clone(), bundle_c.clone()).unwrap();
+        let bundle_ids: Vec<_> = consignment
+            .anchored_bundles()
+            .map(|(_, bundle)| bundle.bundle_id())
+            .collect();
+        assert_eq!(bundle_ids, vec![bundle_a.bundle_id(), bundle_c.bundle_id()]);
+        assert_eq!(consignment.endpoints().collect::<Vec<_>>(), vec![&(
+            bundle_c.bundle_id(),
+            beneficiary
+        )]);
+
+        let resolver = MemTxResolver::with([tx_a, tx_b, tx_c]);
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+
+        assert_eq!(
+            compose_transfer(&contract, anchor_c, bundle_c),
+            Err(Error::MissingHistory(node_a))
+        );
+    }
+
+    #[test]
+    fn concealed_change() {
+        let data = b"media".to_vec();
+        let file = TempFile::with(&data);
+        let contract = Issuance {
+            attachments: vec![file.attachment()],
+            ..Issuance::with(&[(0, 5), (1, 5)])
+        }
+        .contract();
+        let attachment_id = AttachmentId::commit(&sha256::Hash::hash(&data));
+        let mut source: ConsignmentData =
+            strict_deserialize(strict_serialize(&contract).unwrap()).unwrap();
+        source.data_containers = bmap! { attachment_id => LargeVec::try_from(data).unwrap() };
+        let source: Contract = strict_deserialize(strict_serialize(&source).unwrap()).unwrap();
+
+        let beneficiary = SealEndpoint::ConcealedUtxo(seal(4).commit_conceal());
+        let transition = Asset::try_from(&source)
+            .unwrap()
+            .transfer(
+                bset![outpoint(0)],
+                bmap! { beneficiary => 3 },
+                bmap! { seal(5) => 2 },
+            )
+            .unwrap();
+        let (anchor, bundle, tx) = anchor(&source, transition, outpoint(0));
+        let consignment = compose_transfer(&source, anchor, bundle).unwrap();
+
+        let (_, bundle) = consignment.anchored_bundles().next().unwrap();
+        let (transition, _) = bundle.revealed_iter().next().unwrap();
+        assert!(transition
+            .owned_rights()
+            .iter()
+            .all(|(_, assignments)| assignments.filter_revealed_seals().is_empty()));
+        assert_eq!(
+            data_containers(&consignment)
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&attachment_id]
+        );
+
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([tx]));
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+    }
+}
@@ -13,6 +13,7 @@ use rgb::{
 use seals::txout::CloseMethod;
 use stens::AsciiString;
 
+use crate::ricardian::RicardianContract;
 use crate::schema;
 use crate::schema::{FieldType, OwnedRightType};
 
@@ -30,6 +31,9 @@ pub struct FileAttachment {
 #[allow(clippy::too_many_arguments)]
 pub trait Rgb121<'consignment>: Consignment<'consignment> {
     /// Performs primary asset issue, producing [`Contract`] consignment.
+    ///
+    /// Outpoints from `inflation` receive inflation rights, allowing secondary
+    /// issue of the specified amounts with [`crate::Asset::inflate`].
     fn create_rgb121(
         chain: Chain,
         name: AsciiString,
@@ -38,8 +42,11 @@ pub trait Rgb121<'consignment>: Consignment<'consignment> {
         parent_id: Option<AsciiString>,
         file_attachments: Vec<FileAttachment>,
         bytes_data_vec: Vec<Vec<u8>>,
+        data_format: Option<u16>,
         allocations: OutpointValueVec,
+        inflation: OutpointValueVec,
         method: CloseMethod,
+        ricardian_contract: Option<RicardianContract>,
     ) -> Result<Contract, Error>;
 }
 
@@ -52,8 +59,11 @@ impl<'consignment> Rgb121<'consignment> for Contract {
         parent_id: Option<AsciiString>,
         file_attachments: Vec<FileAttachment>,
         bytes_data_vec: Vec<Vec<u8>>,
+        data_format: Option<u16>,
         allocations: OutpointValueVec,
+        inflation: OutpointValueVec,
         method: CloseMethod,
+        ricardian_contract: Option<RicardianContract>,
     ) -> Result<Contract, Error> {
         let now = Utc::now().timestamp();
         let mut metadata = type_map! {
@@ -66,6 +76,13 @@ impl<'consignment> Rgb121<'consignment> for Contract {
             metadata.insert(FieldType::Description.into(), field!(AsciiString, desc));
         };
 
+        if let Some(contract) = ricardian_contract {
+            metadata.insert(
+                FieldType::RicardianContract.into(),
+                field!(AsciiString, contract.to_ascii_string()),
+            );
+        };
+
         if let Some(pid) = parent_id {
             metadata.insert(FieldType::ParentId.into(), field!(AsciiString, pid));
         };
@@ -76,7 +93,14 @@ impl<'consignment> Rgb121<'consignment> for Contract {
             .collect();
         metadata.insert(FieldType::Data.into(), data);
 
-        let issued_supply = allocations.iter().map(|v| v.value).sum();
+        if let Some(format) = data_format {
+            metadata.insert(FieldType::DataFormat.into(), field!(U16, format));
+        };
+
+        let issued_supply = allocations
+            .iter()
+            .try_fold(0u64, |sum, v| sum.checked_add(v.value))
+            .ok_or(Error::SupplyOverflow)?;
         let mut owned_rights = BTreeMap::new();
         owned_rights.insert(
             OwnedRightType::Assets.into(),
@@ -100,7 +124,34 @@ impl<'consignment> Rgb121<'consignment> for Contract {
         );
         metadata.insert(FieldType::IssuedSupply.into(), field!(U64, issued_supply));
 
+        if !inflation.is_empty() {
+            let allowed = inflation
+                .iter()
+                .try_fold(0u64, |sum, v| sum.checked_add(v.value))
+                .ok_or(Error::InflationOverflow)?;
+            owned_rights.insert(
+                OwnedRightType::Inflation.into(),
+                TypedAssignments::zero_balanced(
+                    vec![value::Revealed {
+                        value: allowed,
+                        blinding: secp256k1zkp::key::ONE_KEY.into(),
+                    }],
+                    inflation
+                        .into_iter()
+                        .map(|outpoint_value| {
+                            (
+                                rgb::seal::Revealed::new(method, outpoint_value.outpoint),
+                                outpoint_value.value,
+                            )
+                        })
+                        .collect(),
+                    empty![],
+                ),
+            );
+        }
+
         let outpoints: Vec<OutPoint> = allocations.iter().map(|a| a.outpoint).collect();
+        let mut attachments = vec![];
         for file in file_attachments {
             let file_bytes = std::fs::read(file.file_path.clone()).map_err(|_| {
                 Error::InvalidFileAttachment(file.file_path.to_string_lossy().to_string())
@@ -108,20 +159,23 @@ impl<'consignment> Rgb121<'consignment> for Contract {
             let file_hash = sha256::Hash::hash(&file_bytes[..]);
             let attachment_id = AttachmentId::commit(&file_hash);
 
-            for outpoint in outpoints.clone() {
-                owned_rights.insert(
-                    OwnedRightType::Engraving.into(),
-                    TypedAssignments::Attachment(vec![Assignment::Revealed {
-                        seal: outpoint.into(),
-                        state: rgb::contract::attachment::Revealed {
-                            id: attachment_id,
-                            mime: file.mime.clone(),
-                            salt: file.salt,
-                        },
-                    }]),
-                );
+            for outpoint in &outpoints {
+                attachments.push(Assignment::Revealed {
+                    seal: rgb::seal::Revealed::new(method, *outpoint),
+                    state: rgb::contract::attachment::Revealed {
+                        id: attachment_id,
+                        mime: file.mime.clone(),
+                        salt: file.salt,
+                    },
+                });
             }
         }
+        if !attachments.is_empty() {
+            owned_rights.insert(
+                OwnedRightType::Engraving.into(),
+                TypedAssignments::Attachment(attachments),
+            );
+        }
 
         let schema = schema::schema();
 
@@ -150,4 +204,59 @@ impl<'consignment> Rgb121<'consignment> for Contract {
 pub enum Error {
     /// The provided file attachment {0} is invalid
     InvalidFileAttachment(String),
+
+    /// The sum of the issued allocations exceeds the maximal supply value
+    SupplyOverflow,
+
+    /// The sum of the amounts allowed by inflation rights exceeds the maximal
+    /// supply value
+    InflationOverflow,
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::test_helpers::{Issuance, TempFile};
+    use crate::Asset;
+
+    fn issue(amounts: &[u64]) -> Result<Contract, Error> {
+        let allocations = (0..).zip(amounts.iter().copied()).collect::<Vec<_>>();
+        Issuance::with(&allocations).create()
+    }
+
+    #[test]
+    fn multiple_attachments() {
+        let files = [TempFile::with("first"), TempFile::with("second")];
+        let contract = Issuance {
+            attachments: files.iter().map(TempFile::attachment).collect(),
+            ..Issuance::with(&[(0, 1)])
+        }
+        .contract();
+        let asset = Asset::try_from(&contract).unwrap();
+        assert_eq!(asset.known_attachments().count(), 2);
+    }
+
+    #[test]
+    fn supply_overflow() {
+        assert_eq!(issue(&[u64::MAX, 1]).unwrap_err(), Error::SupplyOverflow);
+        let asset = Asset::try_from(&issue(&[u64::MAX]).unwrap()).unwrap();
+        assert_eq!(asset.issued_supply(), Ok(u64::MAX));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn supply_near_max(a in u64::MAX / 2..=u64::MAX, b in 0..=u64::MAX / 2 + 1) {
+            match a.checked_add(b) {
+                Some(supply) => {
+                    let asset = Asset::try_from(&issue(&[a, b]).unwrap()).unwrap();
+                    prop_assert_eq!(asset.issued_supply(), Ok(supply));
+                }
+                None => prop_assert_eq!(issue(&[a, b]).unwrap_err(), Error::SupplyOverflow),
+            }
+        }
+    }
 }
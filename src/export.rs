@@ -0,0 +1,362 @@
+//! Export of RGB121 schemata in binary, textual and human-readable formats.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use lnpbp::bech32::Bech32ZipString;
+use rgb::schema::{
+    DiscreteFiniteFieldFormat, Occurrences, Schema, SchemaId, StateSchema, TransitionSchema,
+};
+use rgb::{ConsignmentType, InmemConsignment};
+use strict_encoding::StrictEncode;
+
+use crate::schema::{FieldType, OwnedRightType, TransitionType};
+
+/// Formats for exporting RGB121 schemata
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum SchemaFormat {
+    /// Bech32 string of the zipped strict-encoded schema data
+    #[display("bech32")]
+    Bech32,
+
+    /// Binary strict-encoded schema data
+    #[display("strict-bin")]
+    StrictBin,
+
+    /// YAML representation of the schema structure
+    #[cfg(feature = "serde")]
+    #[display("yaml")]
+    Yaml,
+
+    /// JSON representation of the schema structure
+    #[cfg(feature = "serde")]
+    #[display("json")]
+    Json,
+
+    /// Markdown document describing schema fields, owned rights and state
+    /// transitions
+    #[display("markdown")]
+    Markdown,
+}
+
+impl FromStr for SchemaFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "bech32" => SchemaFormat::Bech32,
+            "strict-bin" | "strict" | "bin" => SchemaFormat::StrictBin,
+            #[cfg(feature = "serde")]
+            "yaml" | "yml" => SchemaFormat::Yaml,
+            #[cfg(feature = "serde")]
+            "json" => SchemaFormat::Json,
+            "markdown" | "md" => SchemaFormat::Markdown,
+            _ => return Err(Error::UnknownFormat(s.to_owned())),
+        })
+    }
+}
+
+/// Formats for storing and exchanging consignments
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum ConsignmentFormat {
+    /// Binary strict-encoded consignment data
+    #[display("strict")]
+    Strict,
+
+    /// Bech32 string of the zipped strict-encoded consignment data
+    #[display("bech32")]
+    Bech32,
+
+    /// YAML representation of the consignment
+    #[cfg(feature = "serde")]
+    #[display("yaml")]
+    Yaml,
+
+    /// JSON representation of the consignment
+    #[cfg(feature = "serde")]
+    #[display("json")]
+    Json,
+}
+
+impl FromStr for ConsignmentFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "strict" | "strict-bin" | "bin" => ConsignmentFormat::Strict,
+            "bech32" => ConsignmentFormat::Bech32,
+            #[cfg(feature = "serde")]
+            "yaml" | "yml" => ConsignmentFormat::Yaml,
+            #[cfg(feature = "serde")]
+            "json" => ConsignmentFormat::Json,
+            _ => return Err(Error::UnknownFormat(s.to_owned())),
+        })
+    }
+}
+
+/// Errors happening during schema or consignment export
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// unknown format '{0}'
+    UnknownFormat(String),
+
+    /// strict encoding failure: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+
+    /// serialization failure: {0}
+    Serialization(String),
+}
+
+/// Exports schema in the given format. For all formats except
+/// [`SchemaFormat::StrictBin`] the returned data are UTF-8 strings.
+pub fn export_schema(schema: &Schema, format: SchemaFormat) -> Result<Vec<u8>, Error> {
+    Ok(match format {
+        SchemaFormat::Bech32 => schema.strict_serialize()?.bech32_zip_string().into_bytes(),
+        SchemaFormat::StrictBin => schema.strict_serialize()?,
+        #[cfg(feature = "serde")]
+        SchemaFormat::Yaml => to_yaml(schema)?.into_bytes(),
+        #[cfg(feature = "serde")]
+        SchemaFormat::Json => serde_json::to_string_pretty(schema)
+            .map_err(|err| Error::Serialization(err.to_string()))?
+            .into_bytes(),
+        SchemaFormat::Markdown => schema_markdown(schema).into_bytes(),
+    })
+}
+
+/// Exports consignment (like [`rgb::Contract`] or [`rgb::StateTransfer`]) in
+/// the given format. For all formats except [`ConsignmentFormat::Strict`] the
+/// returned data are UTF-8 strings.
+pub fn export_consignment<T>(
+    consignment: &InmemConsignment<T>,
+    format: ConsignmentFormat,
+) -> Result<Vec<u8>, Error>
+where
+    T: ConsignmentType,
+{
+    Ok(match format {
+        ConsignmentFormat::Strict => consignment.strict_serialize()?,
+        ConsignmentFormat::Bech32 => consignment
+            .strict_serialize()?
+            .bech32_zip_string()
+            .into_bytes(),
+        #[cfg(feature = "serde")]
+        ConsignmentFormat::Yaml => to_yaml(consignment)?.into_bytes(),
+        #[cfg(feature = "serde")]
+        ConsignmentFormat::Json => serde_json::to_string_pretty(consignment)
+            .map_err(|err| Error::Serialization(err.to_string()))?
+            .into_bytes(),
+    })
+}
+
+/// Serializes data into YAML. Since YAML serializer does not support nested
+/// enums, which are widely used by RGB data structures, the data are converted
+/// into JSON value representation first.
+#[cfg(feature = "serde")]
+pub(crate) fn to_yaml(data: &impl serde::Serialize) -> Result<String, Error> {
+    serde_json::to_value(data)
+        .map_err(|err| Error::Serialization(err.to_string()))
+        .and_then(|value| {
+            serde_yaml::to_string(&value).map_err(|err| Error::Serialization(err.to_string()))
+        })
+}
+
+/// Produces markdown document describing all schema fields, owned rights,
+/// genesis and state transitions together with their occurrences
+pub fn schema_markdown(schema: &Schema) -> String {
+    let mut md = String::new();
+    let _ = write_markdown(&mut md, schema);
+    md
+}
+
+fn write_markdown(md: &mut String, schema: &Schema) -> std::fmt::Result {
+    writeln!(md, "# RGB121 schema\n")?;
+    writeln!(md, "- Schema id: `{}`", schema.schema_id())?;
+    if schema.root_id != SchemaId::default() {
+        writeln!(md, "- Root schema id: `{}`", schema.root_id)?;
+    }
+
+    writeln!(md, "\n## Fields\n")?;
+    writeln!(md, "| Field | Type id | Data type |")?;
+    writeln!(md, "|-------|---------|-----------|")?;
+    for (ty, type_ref) in &schema.field_types {
+        writeln!(
+            md,
+            "| {} | `{:#06x}` | `{}` |",
+            field_name(*ty),
+            ty,
+            type_ref
+        )?;
+    }
+
+    writeln!(md, "\n## Owned rights\n")?;
+    writeln!(md, "| Owned right | Type id | State |")?;
+    writeln!(md, "|-------------|---------|-------|")?;
+    for (ty, state) in &schema.owned_right_types {
+        writeln!(
+            md,
+            "| {} | `{:#06x}` | {} |",
+            owned_right_name(*ty),
+            ty,
+            state_name(state)
+        )?;
+    }
+
+    writeln!(md, "\n## Genesis\n")?;
+    write_occurrences(
+        md,
+        "Metadata",
+        "Field",
+        &schema.genesis.metadata,
+        field_name,
+    )?;
+    write_occurrences(
+        md,
+        "Owned rights",
+        "Owned right",
+        &schema.genesis.owned_rights,
+        owned_right_name,
+    )?;
+
+    writeln!(md, "\n## State transitions")?;
+    for (ty, transition) in &schema.transitions {
+        writeln!(md, "\n### {} (`{:#06x}`)\n", transition_name(*ty), ty)?;
+        write_transition(md, transition)?;
+    }
+    Ok(())
+}
+
+fn write_transition(md: &mut String, transition: &TransitionSchema) -> std::fmt::Result {
+    write_occurrences(md, "Metadata", "Field", &transition.metadata, field_name)?;
+    write_occurrences(
+        md,
+        "Closes",
+        "Owned right",
+        &transition.closes,
+        owned_right_name,
+    )?;
+    write_occurrences(
+        md,
+        "Owned rights",
+        "Owned right",
+        &transition.owned_rights,
+        owned_right_name,
+    )
+}
+
+fn write_occurrences(
+    md: &mut String,
+    title: &str,
+    column: &str,
+    items: &BTreeMap<u16, Occurrences>,
+    name: fn(u16) -> String,
+) -> std::fmt::Result {
+    writeln!(md, "{}:\n", title)?;
+    if items.is_empty() {
+        return writeln!(md, "_none_\n");
+    }
+    writeln!(md, "| {} | Occurrences |", column)?;
+    writeln!(md, "|{}|-------------|", "-".repeat(column.len() + 2))?;
+    for (ty, occurrences) in items {
+        writeln!(md, "| {} | {} |", name(*ty), occurrences_range(occurrences))?;
+    }
+    writeln!(md)
+}
+
+pub(crate) fn occurrences_range(occurrences: &Occurrences) -> String {
+    let min = occurrences.min_value();
+    match occurrences.max_value() {
+        u16::MAX => format!("{}..*", min),
+        max if max == min => format!("{}", min),
+        max => format!("{}..{}", min, max),
+    }
+}
+
+pub(crate) fn state_name(state: &StateSchema) -> String {
+    match state {
+        StateSchema::Declarative => s!("declarative"),
+        StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit) => {
+            s!("confidential amount (`u64`)")
+        }
+        StateSchema::CustomData(type_ref) => format!("custom data (`{}`)", type_ref),
+        StateSchema::DataContainer => s!("attachment"),
+    }
+}
+
+pub(crate) fn field_name(ty: u16) -> String {
+    [
+        FieldType::Name,
+        FieldType::RicardianContract,
+        FieldType::Description,
+        FieldType::Data,
+        FieldType::DataFormat,
+        FieldType::Precision,
+        FieldType::IssuedSupply,
+        FieldType::Timestamp,
+        FieldType::ParentId,
+    ]
+    .into_iter()
+    .find(|field| *field as u16 == ty)
+    .map(|field| field.to_string())
+    .unwrap_or_else(|| format!("Unknown `{:#06x}`", ty))
+}
+
+pub(crate) fn owned_right_name(ty: u16) -> String {
+    [OwnedRightType::Assets, OwnedRightType::Engraving, OwnedRightType::Inflation]
+        .into_iter()
+        .find(|right| *right as u16 == ty)
+        .map(|right| right.to_string())
+        .unwrap_or_else(|| format!("Unknown `{:#06x}`", ty))
+}
+
+pub(crate) fn transition_name(ty: u16) -> String {
+    [TransitionType::Issue, TransitionType::Transfer, TransitionType::Engraving]
+        .into_iter()
+        .find(|transition| *transition as u16 == ty)
+        .map(|transition| transition.to_string())
+        .unwrap_or_else(|| format!("Unknown `{:#06x}`", ty))
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictDecode;
+
+    use super::*;
+    use crate::{schema, subschema};
+
+    #[test]
+    fn format_roundtrip() {
+        for format in [
+            SchemaFormat::Bech32,
+            SchemaFormat::StrictBin,
+            #[cfg(feature = "serde")]
+            SchemaFormat::Yaml,
+            #[cfg(feature = "serde")]
+            SchemaFormat::Json,
+            SchemaFormat::Markdown,
+        ] {
+            assert_eq!(SchemaFormat::from_str(&format.to_string()), Ok(format));
+            assert!(export_schema(&schema(), format).is_ok());
+        }
+        assert!(SchemaFormat::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn strict_bin() {
+        let data = export_schema(&schema(), SchemaFormat::StrictBin).unwrap();
+        assert_eq!(Schema::strict_deserialize(data).unwrap(), schema());
+    }
+
+    #[test]
+    fn markdown() {
+        let md = schema_markdown(&subschema());
+        assert!(md.contains(&subschema().schema_id().to_string()));
+        assert!(md.contains("| IssuedSupply | 1 |"));
+        assert!(md.contains("| Assets | `0x00a1` | confidential amount (`u64`) |"));
+        assert!(md.contains("### Transfer (`0x0000`)"));
+        assert!(md.contains("| Assets | 1..* |"));
+        assert!(!md.contains("### Engraving"));
+    }
+}
@@ -0,0 +1,430 @@
+//! Import of schemata received from third parties and their verification
+//! against the built-in RGB121 schema definitions.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
+
+use lnpbp::bech32::{self, FromBech32ZipStr};
+use rgb::schema::{Occurrences, Schema, TransitionSchema};
+use rgb::{ConsignmentType, Contract, InmemConsignment};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::export::{field_name, occurrences_range, owned_right_name, state_name, transition_name};
+use crate::{ConsignmentFormat, SchemaInfo, SchemaRegistry};
+
+/// Errors happening during schema or consignment import
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// invalid Bech32 schema representation: {0}
+    #[from]
+    Bech32(bech32::Error),
+
+    /// invalid strict-encoded data: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+
+    /// invalid consignment data: {0}
+    Deserialization(String),
+}
+
+/// Decodes schema from either a Bech32 (`z1...`) string or binary
+/// strict-encoded data, detecting the format automatically
+pub fn import_schema(data: &[u8]) -> Result<Schema, Error> {
+    match std::str::from_utf8(data).map(str::trim) {
+        Ok(s) if s.starts_with("z1") => {
+            let data = Vec::<u8>::from_bech32_zip_str(s)?;
+            Ok(Schema::strict_deserialize(data)?)
+        }
+        _ => Ok(Schema::strict_deserialize(data)?),
+    }
+}
+
+/// Decodes consignment from the data in the given format. If the format is not
+/// specified, it is detected automatically.
+pub fn import_consignment<T>(
+    data: &[u8],
+    format: Option<ConsignmentFormat>,
+) -> Result<InmemConsignment<T>, Error>
+where
+    T: ConsignmentType,
+{
+    let text = std::str::from_utf8(data).map(str::trim);
+    let format = format.unwrap_or_else(|| detect_format::<T>(data));
+    match format {
+        ConsignmentFormat::Strict => Ok(InmemConsignment::strict_deserialize(data)?),
+        ConsignmentFormat::Bech32 => {
+            let s = text.map_err(|err| Error::Deserialization(err.to_string()))?;
+            let data = match Contract::from_str(s) {
+                Ok(contract) => contract.strict_serialize()?,
+                Err(_) => Vec::<u8>::from_bech32_zip_str(s)?,
+            };
+            Ok(InmemConsignment::strict_deserialize(data)?)
+        }
+        #[cfg(feature = "serde")]
+        ConsignmentFormat::Yaml => serde_yaml::from_slice::<serde_json::Value>(data)
+            .map_err(|err| Error::Deserialization(err.to_string()))
+            .and_then(|value| {
+                serde_json::from_value(value).map_err(|err| Error::Deserialization(err.to_string()))
+            }),
+        #[cfg(feature = "serde")]
+        ConsignmentFormat::Json => {
+            serde_json::from_slice(data).map_err(|err| Error::Deserialization(err.to_string()))
+        }
+    }
+}
+
+fn detect_format<T>(data: &[u8]) -> ConsignmentFormat
+where T: ConsignmentType {
+    match std::str::from_utf8(data).map(str::trim) {
+        Ok(s) if s.starts_with("z1") || s.starts_with("rgbc1") => ConsignmentFormat::Bech32,
+        #[cfg(feature = "serde")]
+        Ok(s) if s.starts_with('{') => ConsignmentFormat::Json,
+        _ if InmemConsignment::<T>::strict_deserialize(data).is_ok() => ConsignmentFormat::Strict,
+        #[cfg(feature = "serde")]
+        Ok(_) => ConsignmentFormat::Yaml,
+        _ => ConsignmentFormat::Strict,
+    }
+}
+
+/// Single difference between a schema and the RGB121 schema it is compared
+/// against
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SchemaDifference {
+    /// Path of the differing schema item, like `genesis.metadata.Name`
+    pub path: String,
+    /// Value defined by RGB121 schema, or `None` if the item is absent from it
+    pub expected: Option<String>,
+    /// Value defined by the verified schema, or `None` if the item is absent
+    pub actual: Option<String>,
+}
+
+impl Display for SchemaDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            self.path,
+            self.expected.as_deref().unwrap_or("nothing"),
+            self.actual.as_deref().unwrap_or("nothing")
+        )
+    }
+}
+
+/// Result of verification of a schema against RGB121 schemata
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SchemaVerification {
+    /// RGB121 schema which is the closest to the verified one
+    pub reference: SchemaInfo,
+    /// List of differences from the reference schema; empty if the verified
+    /// schema is identical to it
+    pub differences: Vec<SchemaDifference>,
+}
+
+impl SchemaVerification {
+    /// Detects whether the verified schema is one of RGB121 schemata
+    pub fn is_rgb121(&self) -> bool { self.differences.is_empty() }
+}
+
+impl Display for SchemaVerification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_rgb121() {
+            return write!(
+                f,
+                "schema matches RGB121 {} {}",
+                self.reference.kind, self.reference.version
+            );
+        }
+        writeln!(
+            f,
+            "schema differs from RGB121 {} {}:",
+            self.reference.kind, self.reference.version
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "- {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares schema against all known RGB121 schemata field by field, reporting
+/// differences from the closest one
+pub fn verify_schema(schema: &Schema) -> SchemaVerification {
+    SchemaRegistry::new()
+        .iter()
+        .map(|info| SchemaVerification {
+            reference: *info,
+            differences: schema_diff(&info.schema(), schema),
+        })
+        .min_by_key(|verification| verification.differences.len())
+        .expect("schema registry is never empty")
+}
+
+/// Lists all differences of the `actual` schema from the `expected` one
+pub fn schema_diff(expected: &Schema, actual: &Schema) -> Vec<SchemaDifference> {
+    let mut diff = Diff::default();
+    diff.value("rgb_features", &expected.rgb_features, &actual.rgb_features);
+    diff.value("root_id", &expected.root_id, &actual.root_id);
+    diff.value("type_system", &expected.type_system, &actual.type_system);
+    diff.map(
+        "field_types",
+        &expected.field_types,
+        &actual.field_types,
+        field_name,
+        |ty| ty.to_string(),
+    );
+    diff.map(
+        "owned_right_types",
+        &expected.owned_right_types,
+        &actual.owned_right_types,
+        owned_right_name,
+        state_name,
+    );
+    diff.set(
+        "public_right_types",
+        &expected.public_right_types,
+        &actual.public_right_types,
+    );
+    diff.occurrences(
+        "genesis.metadata",
+        &expected.genesis.metadata,
+        &actual.genesis.metadata,
+        field_name,
+    );
+    diff.occurrences(
+        "genesis.owned_rights",
+        &expected.genesis.owned_rights,
+        &actual.genesis.owned_rights,
+        owned_right_name,
+    );
+    diff.set(
+        "genesis.public_rights",
+        &expected.genesis.public_rights,
+        &actual.genesis.public_rights,
+    );
+    diff.set(
+        "extensions",
+        &expected.extensions.keys().copied().collect(),
+        &actual.extensions.keys().copied().collect(),
+    );
+    let transitions: BTreeSet<_> = expected
+        .transitions
+        .keys()
+        .chain(actual.transitions.keys())
+        .collect();
+    for ty in transitions {
+        let path = format!("transitions.{}", transition_name(*ty));
+        match (expected.transitions.get(ty), actual.transitions.get(ty)) {
+            (Some(expected), Some(actual)) if expected != actual => {
+                diff.transition(&path, expected, actual)
+            }
+            (Some(_), None) => diff.push(path, Some(s!("transition")), None),
+            (None, Some(_)) => diff.push(path, None, Some(s!("transition"))),
+            _ => {}
+        }
+    }
+    if expected.script != actual.script {
+        diff.push(
+            s!("script"),
+            Some(format!("{} script", expected.script.vm_type())),
+            Some(format!("different {} script", actual.script.vm_type())),
+        );
+    }
+    diff.value(
+        "override_rules",
+        &expected.override_rules,
+        &actual.override_rules,
+    );
+    diff.0
+}
+
+#[derive(Default)]
+struct Diff(Vec<SchemaDifference>);
+
+impl Diff {
+    fn push(&mut self, path: String, expected: Option<String>, actual: Option<String>) {
+        self.0.push(SchemaDifference {
+            path,
+            expected,
+            actual,
+        });
+    }
+
+    fn value<T>(&mut self, path: &str, expected: &T, actual: &T)
+    where T: PartialEq + Debug {
+        if expected != actual {
+            self.push(
+                path.to_owned(),
+                Some(format!("{:?}", expected)),
+                Some(format!("{:?}", actual)),
+            );
+        }
+    }
+
+    fn set(&mut self, path: &str, expected: &BTreeSet<u16>, actual: &BTreeSet<u16>) {
+        for ty in expected.symmetric_difference(actual) {
+            let present = Some(s!("present"));
+            let (expected, actual) =
+                if expected.contains(ty) { (present, None) } else { (None, present) };
+            self.push(format!("{}.{:#06x}", path, ty), expected, actual);
+        }
+    }
+
+    fn map<V>(
+        &mut self,
+        path: &str,
+        expected: &BTreeMap<u16, V>,
+        actual: &BTreeMap<u16, V>,
+        name: fn(u16) -> String,
+        value: impl Fn(&V) -> String,
+    ) where
+        V: PartialEq,
+    {
+        let keys: BTreeSet<_> = expected.keys().chain(actual.keys()).collect();
+        for ty in keys {
+            let (expected, actual) = (expected.get(ty), actual.get(ty));
+            if expected != actual {
+                self.push(
+                    format!("{}.{}", path, name(*ty)),
+                    expected.map(&value),
+                    actual.map(&value),
+                );
+            }
+        }
+    }
+
+    fn occurrences(
+        &mut self,
+        path: &str,
+        expected: &BTreeMap<u16, Occurrences>,
+        actual: &BTreeMap<u16, Occurrences>,
+        name: fn(u16) -> String,
+    ) {
+        self.map(path, expected, actual, name, occurrences_range)
+    }
+
+    fn transition(&mut self, path: &str, expected: &TransitionSchema, actual: &TransitionSchema) {
+        self.occurrences(
+            &format!("{}.metadata", path),
+            &expected.metadata,
+            &actual.metadata,
+            field_name,
+        );
+        self.occurrences(
+            &format!("{}.closes", path),
+            &expected.closes,
+            &actual.closes,
+            owned_right_name,
+        );
+        self.occurrences(
+            &format!("{}.owned_rights", path),
+            &expected.owned_rights,
+            &actual.owned_rights,
+            owned_right_name,
+        );
+        self.set(
+            &format!("{}.public_rights", path),
+            &expected.public_rights,
+            &actual.public_rights,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rgb::schema::Occurrences;
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::schema::TransitionType;
+    use crate::test_helpers::Issuance;
+    use crate::{
+        export_consignment, export_schema, schema, subschema, FieldType, SchemaFormat, SchemaKind,
+    };
+
+    #[test]
+    fn import_formats() {
+        for format in [SchemaFormat::Bech32, SchemaFormat::StrictBin] {
+            let data = export_schema(&subschema(), format).unwrap();
+            assert_eq!(import_schema(&data), Ok(subschema()));
+        }
+        assert!(import_schema(b"z1invalid").is_err());
+    }
+
+    #[test]
+    fn consignment_formats() {
+        let contract = Issuance {
+            method: CloseMethod::TapretFirst,
+            ..Issuance::with(&[(0, 1)])
+        }
+        .contract();
+        for format in [
+            ConsignmentFormat::Strict,
+            ConsignmentFormat::Bech32,
+            #[cfg(feature = "serde")]
+            ConsignmentFormat::Yaml,
+            #[cfg(feature = "serde")]
+            ConsignmentFormat::Json,
+        ] {
+            let data = export_consignment(&contract, format).unwrap();
+            assert_eq!(
+                import_consignment(&data, Some(format)),
+                Ok(contract.clone())
+            );
+            assert_eq!(import_consignment(&data, None), Ok(contract.clone()));
+        }
+        let data = contract.to_string().into_bytes();
+        assert_eq!(import_consignment(&data, None), Ok(contract));
+    }
+
+    #[test]
+    fn verify_known() {
+        let verification = verify_schema(&subschema());
+        assert!(verification.is_rgb121());
+        assert_eq!(verification.reference.kind, SchemaKind::Subschema);
+    }
+
+    #[test]
+    fn verify_modified() {
+        let mut modified = schema();
+        modified
+            .genesis
+            .metadata
+            .insert(FieldType::IssuedSupply.into(), Occurrences::NoneOrOnce);
+        modified.field_types.remove(&FieldType::ParentId.into());
+
+        let verification = verify_schema(&modified);
+        assert!(!verification.is_rgb121());
+        assert_eq!(verification.reference.kind, SchemaKind::Root);
+        assert_eq!(verification.differences, vec![
+            SchemaDifference {
+                path: s!("field_types.ParentId"),
+                expected: Some(s!("AsciiChar*")),
+                actual: None,
+            },
+            SchemaDifference {
+                path: s!("genesis.metadata.IssuedSupply"),
+                expected: Some(s!("1")),
+                actual: Some(s!("0..1")),
+            },
+        ]);
+    }
+
+    #[test]
+    fn unknown_transitions() {
+        let mut modified = subschema();
+        let transfer = modified.transitions[&TransitionType::Transfer.into()].clone();
+        modified.transitions.insert(0x10, transfer.clone());
+        modified.transitions.insert(0x11, transfer);
+
+        let paths = schema_diff(&subschema(), &modified)
+            .into_iter()
+            .map(|difference| difference.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec![
+            s!("transitions.Unknown `0x0010`"),
+            s!("transitions.Unknown `0x0011`")
+        ]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Human- and machine-readable description of RGB121 consignments, used by
+//! tools explaining the content of genesis and state transfer files.
+
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::{OutPoint, Txid};
+use rgb::{
+    AttachmentId, BundleId, Consignment, ConsignmentType, ContractId, InmemConsignment, NodeId,
+    NodeOutpoint, OwnedValue, SchemaId, SealEndpoint,
+};
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use crate::{asset, Asset, Provenance, SchemaVersion};
+
+/// Asset metadata defined by the genesis
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct MetadataDetails {
+    /// Asset name
+    pub name: Option<String>,
+    /// Asset description
+    pub description: Option<String>,
+    /// Ricardian contract text, or its hash followed by URL
+    pub ricardian_contract: Option<String>,
+    /// Whether the genesis contains malformed Ricardian contract data, which
+    /// is not reported in `ricardian_contract` field
+    pub invalid_ricardian_contract: bool,
+    /// Decimal precision
+    pub precision: Option<u8>,
+    /// Supply issued with the genesis
+    pub issued_supply: u64,
+    /// Genesis timestamp
+    pub timestamp: Option<i64>,
+    /// Parent asset id
+    pub parent_id: Option<String>,
+    /// Hex-encoded data blobs
+    pub data: Vec<String>,
+    /// Format of the data blobs
+    pub data_format: Option<u16>,
+}
+
+/// Asset amount allocated to a bitcoin transaction output
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct AllocationDetails {
+    /// Transaction output holding the allocation
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub outpoint: OutPoint,
+    /// Allocated amount
+    pub amount: u64,
+    /// Node assigning the allocation
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_outpoint: NodeOutpoint,
+}
+
+/// Attachment engraved into the asset and assigned to a transaction output
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct AttachmentDetails {
+    /// Transaction output holding the attachment
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub outpoint: OutPoint,
+    /// Attachment id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub id: AttachmentId,
+    /// Attachment MIME type
+    pub mime: String,
+    /// Attachment salt
+    pub salt: u64,
+}
+
+/// State transition present in the consignment history
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct TransitionDetails {
+    /// Transition id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_id: NodeId,
+    /// Name of the transition type
+    pub transition_type: String,
+    /// Witness transaction id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub witness_txid: Txid,
+    /// Owned rights closed by the transition
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<DisplayFromStr>>"))]
+    pub inputs: Vec<NodeOutpoint>,
+    /// Number of owned rights assigned by the transition
+    pub outputs: usize,
+}
+
+/// Consignment endpoint: seal receiving the state transferred by a bundle
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct EndpointDetails {
+    /// Bundle assigning state to the seal
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub bundle_id: BundleId,
+    /// Seal, which may be concealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub seal: SealEndpoint,
+}
+
+/// Complete description of RGB121 consignment
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ConsignmentDetails {
+    /// Asset contract id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub contract_id: ContractId,
+    /// Asset schema id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub schema_id: SchemaId,
+    /// Version of RGB121 schema
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub version: SchemaVersion,
+    /// Asset metadata
+    pub metadata: MetadataDetails,
+    /// Known (unspent) asset allocations
+    pub allocations: Vec<AllocationDetails>,
+    /// Known (unspent) inflation rights, with the amount allowed to be issued
+    /// by closing them
+    pub inflation: Vec<AllocationDetails>,
+    /// Known (unspent) engraved attachments
+    pub attachments: Vec<AttachmentDetails>,
+    /// State transitions in the consignment history, each of which goes
+    /// after all the transitions it spends from (see [`Provenance`])
+    pub history: Vec<TransitionDetails>,
+    /// Consignment endpoints
+    pub endpoints: Vec<EndpointDetails>,
+}
+
+impl ConsignmentDetails {
+    /// Extracts details of RGB121 asset from the consignment
+    pub fn with<T>(consignment: &InmemConsignment<T>) -> Result<Self, asset::Error>
+    where T: ConsignmentType {
+        let asset = Asset::try_from(consignment)?;
+        let ricardian_contract = asset.ricardian_contract();
+        let metadata = MetadataDetails {
+            name: asset.name().map(|s| s.to_string()),
+            description: asset.description().map(|s| s.to_string()),
+            ricardian_contract: ricardian_contract
+                .clone()
+                .ok()
+                .flatten()
+                .map(|c| c.to_string()),
+            invalid_ricardian_contract: ricardian_contract.is_err(),
+            precision: asset.precision(),
+            issued_supply: asset.issued_supply()?,
+            timestamp: asset.timestamp(),
+            parent_id: asset.parent_id().map(|s| s.to_string()),
+            data: asset.data().iter().map(|data| data.to_hex()).collect(),
+            data_format: asset.data_format(),
+        };
+        let details = |coin: &OwnedValue| AllocationDetails {
+            outpoint: coin.seal,
+            amount: coin.state.value,
+            node_outpoint: coin.outpoint,
+        };
+        let allocations = asset.known_coins().map(details).collect();
+        let inflation = asset.known_inflation().map(details).collect();
+        let attachments = asset
+            .known_attachments()
+            .map(|attachment| AttachmentDetails {
+                outpoint: attachment.seal,
+                id: attachment.state.id,
+                mime: attachment.state.mime.to_string(),
+                salt: attachment.state.salt,
+            })
+            .collect();
+        let history = Provenance::with(consignment)?
+            .records
+            .into_iter()
+            .filter_map(|record| {
+                Some(TransitionDetails {
+                    node_id: record.node_id,
+                    transition_type: record.node_type,
+                    witness_txid: record.witness_txid?,
+                    inputs: record.inputs,
+                    outputs: record.outputs.len() + record.engravings.len(),
+                })
+            })
+            .collect();
+        let endpoints = consignment
+            .endpoints()
+            .map(|(bundle_id, seal)| EndpointDetails {
+                bundle_id: *bundle_id,
+                seal: *seal,
+            })
+            .collect();
+        Ok(ConsignmentDetails {
+            contract_id: asset.contract_id(),
+            schema_id: asset.schema_id(),
+            version: asset.version().ok_or(asset::Error::WrongSchemaId)?,
+            metadata,
+            allocations,
+            inflation,
+            attachments,
+            history,
+            endpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::test_helpers::Issuance;
+
+    #[test]
+    fn genesis_details() {
+        let contract = Issuance {
+            description: Some("Rare item"),
+            data: vec![vec![0xde, 0xad]],
+            data_format: Some(1),
+            method: CloseMethod::TapretFirst,
+            ..Issuance::with(&[(0, 3)])
+        }
+        .contract();
+        let details = ConsignmentDetails::with(&contract).unwrap();
+        assert_eq!(details.contract_id, contract.contract_id());
+        assert_eq!(details.version, SchemaVersion::V2);
+        assert_eq!(details.metadata.name.as_deref(), Some("Collectible"));
+        assert_eq!(details.metadata.description.as_deref(), Some("Rare item"));
+        assert_eq!(details.metadata.ricardian_contract, None);
+        assert!(!details.metadata.invalid_ricardian_contract);
+        assert_eq!(details.metadata.issued_supply, 3);
+        assert_eq!(details.metadata.data, vec![s!("dead")]);
+        assert_eq!(details.metadata.data_format, Some(1));
+        assert_eq!(details.allocations.len(), 1);
+        assert_eq!(details.allocations[0].amount, 3);
+        assert!(details.inflation.is_empty());
+        assert!(details.history.is_empty());
+        assert!(details.endpoints.is_empty());
+    }
+}
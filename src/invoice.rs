@@ -0,0 +1,321 @@
+//! Invoices requesting transfer of RGB121 assets.
+//!
+//! Invoice is created by the recipient and specifies the asset contract, the
+//! requested amount or a specific token (allocation), and the seal which must
+//! receive the asset. It is represented as URI of the following form:
+//!
+//! ```text
+//! rgb121:<contract_id>?amount=<amount>&seal=<seal>&network=<chain>
+//!     [&expiry=<unix_timestamp>][&attachment=<attachment_id>][&mime=<mime>]
+//! ```
+//!
+//! where `amount` may be replaced with `token=<node_id>/<type>/<no>`.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use amplify::Wrapper;
+use bitcoin::hashes::sha256t;
+use chrono::Utc;
+use lnpbp::chain::Chain;
+use rgb::{seal, AtomicValue, AttachmentId, ContractId, NodeOutpoint, SealEndpoint};
+use url::Url;
+
+/// URI scheme of RGB121 invoices
+pub const INVOICE_SCHEME: &str = "rgb121";
+
+/// Errors parsing RGB121 invoice
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// invoice is not a valid URI: {0}
+    #[from]
+    InvalidUri(url::ParseError),
+
+    /// invoice URI must use `rgb121` scheme instead of `{0}`
+    WrongScheme(String),
+
+    /// invoice contains invalid contract id `{0}`
+    InvalidContractId(String),
+
+    /// invoice parameter `{0}` is required
+    MissingParameter(&'static str),
+
+    /// invoice parameter `{0}` is present more than once
+    RepeatedParameter(String),
+
+    /// unknown invoice parameter `{0}`
+    UnknownParameter(String),
+
+    /// invoice parameter `{name}` has invalid value `{value}`
+    InvalidParameter {
+        /// Parameter name
+        name: &'static str,
+        /// Invalid parameter value
+        value: String,
+    },
+
+    /// invoice must request either an amount or a specific token, but not
+    /// both
+    AmountAndToken,
+}
+
+/// Assets requested by the invoice
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum InvoiceRequest {
+    /// Any allocations of the asset summing to the amount
+    Amount(AtomicValue),
+    /// Specific token, i.e. the allocation assigned by the node output
+    Token(NodeOutpoint),
+}
+
+/// Invoice requesting transfer of RGB121 asset
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Invoice {
+    /// Contract of the requested asset
+    pub contract_id: ContractId,
+    /// Requested amount or token
+    pub request: InvoiceRequest,
+    /// Seal receiving the asset: blinded UTXO or witness transaction output
+    pub seal: SealEndpoint,
+    /// Bitcoin network of the asset
+    pub network: Chain,
+    /// Time after which the invoice must not be paid, as UNIX timestamp
+    pub expiry: Option<i64>,
+    /// Attachment which must be engraved into the transferred asset
+    pub attachment: Option<AttachmentId>,
+    /// MIME type of the attachments engraved into the transferred asset
+    pub mime: Option<String>,
+}
+
+impl Invoice {
+    /// Constructs invoice without expiry and attachment requirements
+    pub fn with(
+        contract_id: ContractId,
+        request: InvoiceRequest,
+        seal: SealEndpoint,
+        network: Chain,
+    ) -> Invoice {
+        Invoice {
+            contract_id,
+            request,
+            seal,
+            network,
+            expiry: None,
+            attachment: None,
+            mime: None,
+        }
+    }
+
+    /// Checks whether the invoice is expired at the given UNIX timestamp
+    pub fn is_expired_at(&self, timestamp: i64) -> bool {
+        self.expiry
+            .map(|expiry| timestamp > expiry)
+            .unwrap_or_default()
+    }
+
+    /// Checks whether the invoice is expired at the current time
+    pub fn is_expired(&self) -> bool { self.is_expired_at(Utc::now().timestamp()) }
+}
+
+impl Display for Invoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        match self.request {
+            InvoiceRequest::Amount(amount) => query.append_pair("amount", &amount.to_string()),
+            InvoiceRequest::Token(token) => query.append_pair("token", &token.to_string()),
+        };
+        query.append_pair("seal", &seal_string(self.seal));
+        query.append_pair("network", &self.network.to_string());
+        if let Some(expiry) = self.expiry {
+            query.append_pair("expiry", &expiry.to_string());
+        }
+        if let Some(attachment) = self.attachment {
+            query.append_pair("attachment", &attachment.to_string());
+        }
+        if let Some(mime) = &self.mime {
+            query.append_pair("mime", mime);
+        }
+        write!(
+            f,
+            "{}:{}?{}",
+            INVOICE_SCHEME,
+            self.contract_id,
+            query.finish()
+        )
+    }
+}
+
+impl FromStr for Invoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s)?;
+        if url.scheme() != INVOICE_SCHEME {
+            return Err(Error::WrongScheme(url.scheme().to_owned()));
+        }
+        let contract_id = ContractId::from_str(url.path())
+            .map_err(|_| Error::InvalidContractId(url.path().to_owned()))?;
+
+        let mut amount = None;
+        let mut token = None;
+        let mut seal = None;
+        let mut network = None;
+        let mut expiry = None;
+        let mut attachment = None;
+        let mut mime = None;
+        for (name, value) in url.query_pairs() {
+            let value = value.into_owned();
+            let repeated = match name.as_ref() {
+                "amount" => amount.replace(parse("amount", value)?).is_some(),
+                "token" => token.replace(parse("token", value)?).is_some(),
+                "seal" => seal.replace(parse("seal", value)?).is_some(),
+                "network" => network.replace(parse("network", value)?).is_some(),
+                "expiry" => expiry.replace(parse("expiry", value)?).is_some(),
+                "attachment" => attachment
+                    .replace(AttachmentId::from_inner(parse::<sha256t::Hash<_>>(
+                        "attachment",
+                        value,
+                    )?))
+                    .is_some(),
+                "mime" => mime.replace(value).is_some(),
+                _ => return Err(Error::UnknownParameter(name.into_owned())),
+            };
+            if repeated {
+                return Err(Error::RepeatedParameter(name.into_owned()));
+            }
+        }
+
+        let request = match (amount, token) {
+            (Some(amount), None) => InvoiceRequest::Amount(amount),
+            (None, Some(token)) => InvoiceRequest::Token(token),
+            (Some(_), Some(_)) => return Err(Error::AmountAndToken),
+            (None, None) => return Err(Error::MissingParameter("amount")),
+        };
+        Ok(Invoice {
+            contract_id,
+            request,
+            seal: seal.ok_or(Error::MissingParameter("seal"))?,
+            network: network.ok_or(Error::MissingParameter("network"))?,
+            expiry,
+            attachment,
+            mime,
+        })
+    }
+}
+
+/// Formats seal endpoint such that it can be parsed back: display of
+/// [`SealEndpoint::WitnessVout`] uses decimal blinding factor, while the parser
+/// requires it to be hexadecimal, as in display of [`seal::Revealed`]
+fn seal_string(seal: SealEndpoint) -> String {
+    match seal {
+        SealEndpoint::ConcealedUtxo(seal) => seal.to_string(),
+        SealEndpoint::WitnessVout {
+            method,
+            vout,
+            blinding,
+        } => seal::Revealed {
+            method,
+            txid: None,
+            vout,
+            blinding,
+        }
+        .to_string(),
+    }
+}
+
+fn parse<T>(name: &'static str, value: String) -> Result<T, Error>
+where T: FromStr {
+    T::from_str(&value).map_err(|_| Error::InvalidParameter { name, value })
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256, Hash};
+    use commit_verify::{CommitConceal, CommitVerify};
+    use rgb::NodeId;
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::test_helpers::outpoint;
+
+    fn invoice() -> Invoice {
+        Invoice::with(
+            ContractId::from_inner(Hash::hash(b"contract")),
+            InvoiceRequest::Amount(5),
+            SealEndpoint::ConcealedUtxo(
+                seal::Revealed::new(CloseMethod::TapretFirst, outpoint(0)).commit_conceal(),
+            ),
+            Chain::Signet,
+        )
+    }
+
+    #[test]
+    fn roundtrip() {
+        let invoice = invoice();
+        let uri = invoice.to_string();
+        assert!(uri.starts_with(&format!("rgb121:{}?amount=5&seal=", invoice.contract_id)));
+        assert!(uri.ends_with("&network=signet"));
+        assert_eq!(Invoice::from_str(&uri), Ok(invoice.clone()));
+
+        let full = Invoice {
+            request: InvoiceRequest::Token(NodeOutpoint::new(
+                NodeId::from_inner(Hash::hash(b"node")),
+                161,
+                1,
+            )),
+            seal: SealEndpoint::WitnessVout {
+                method: CloseMethod::OpretFirst,
+                vout: 1,
+                blinding: 42,
+            },
+            expiry: Some(1_700_000_000),
+            attachment: Some(AttachmentId::commit(&sha256::Hash::hash(b"media"))),
+            mime: Some(s!("image/png")),
+            ..invoice.clone()
+        };
+        assert_eq!(Invoice::from_str(&full.to_string()), Ok(full.clone()));
+        assert!(full.is_expired_at(1_700_000_001));
+        assert!(!full.is_expired_at(1_700_000_000));
+        assert!(!invoice.is_expired());
+    }
+
+    #[test]
+    fn errors() {
+        let uri = invoice().to_string();
+        assert_eq!(
+            Invoice::from_str(&uri.replace("rgb121:", "bitcoin:")),
+            Err(Error::WrongScheme(s!("bitcoin")))
+        );
+        assert_eq!(
+            Invoice::from_str(&uri.replace("amount=5", "amount=-5")),
+            Err(Error::InvalidParameter {
+                name: "amount",
+                value: s!("-5")
+            })
+        );
+        assert_eq!(
+            Invoice::from_str(&format!("{}&amount=5", uri)),
+            Err(Error::RepeatedParameter(s!("amount")))
+        );
+        assert_eq!(
+            Invoice::from_str(&format!("{}&token=invalid", uri)),
+            Err(Error::InvalidParameter {
+                name: "token",
+                value: s!("invalid")
+            })
+        );
+        assert_eq!(
+            Invoice::from_str(&format!("{}&fee=1", uri)),
+            Err(Error::UnknownParameter(s!("fee")))
+        );
+        assert_eq!(
+            Invoice::from_str(&uri.replace("amount=5&", "")),
+            Err(Error::MissingParameter("amount"))
+        );
+        assert_eq!(
+            Invoice::from_str(&uri.replace("&network=signet", "")),
+            Err(Error::MissingParameter("network"))
+        );
+    }
+}
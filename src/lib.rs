@@ -29,17 +29,74 @@ extern crate rgb;
 extern crate stens;
 
 #[cfg(feature = "serde")]
+#[macro_use]
 extern crate serde_crate as serde;
 #[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "serde")]
 extern crate serde_with;
+#[cfg(feature = "serde")]
+extern crate serde_yaml;
 
 mod schema;
 mod create;
 mod asset;
 mod transitions;
+mod ricardian;
+mod rules;
+mod registry;
+mod export;
+mod import;
+mod inspect;
+mod invoice;
+mod compose;
+mod psbt;
+mod provenance;
+mod selection;
+mod batch;
+#[cfg(feature = "sqlite")]
+mod cache;
+mod multi;
+mod stash;
+mod validation;
+#[cfg(test)]
+pub(crate) mod test_helpers;
 
 pub use asset::{Asset, Error};
+pub use batch::{BatchTransfer, Error as BatchError};
+#[cfg(feature = "sqlite")]
+pub use cache::{AssetCache, CachedAllocation, CachedAttachment, Error as CacheError};
+pub use compose::{compose_transfer, Error as ComposeError};
 pub use create::{Error as CreateError, FileAttachment, Rgb121};
+pub use export::{
+    export_consignment, export_schema, schema_markdown, ConsignmentFormat, Error as ExportError,
+    SchemaFormat,
+};
+pub use import::{
+    import_consignment, import_schema, schema_diff, verify_schema, Error as ImportError,
+    SchemaDifference, SchemaVerification,
+};
+pub use inspect::{
+    AllocationDetails, AttachmentDetails, ConsignmentDetails, EndpointDetails, MetadataDetails,
+    TransitionDetails,
+};
+pub use invoice::{Error as InvoiceError, Invoice, InvoiceRequest, INVOICE_SCHEME};
+pub use multi::{transfer_multi, Error as MultiTransferError, MultiTransfer};
+pub use provenance::{Provenance, ProvenanceEngraving, ProvenanceOutput, ProvenanceRecord};
+pub use psbt::{
+    complete_commitment, embed_blank_transitions, embed_transition, extract_anchor,
+    finalize_transfer, Error as PsbtError, PSBT_OUT_LNPBP4_BLOCK, PSBT_RGB121_PREFIX,
+};
+pub use registry::{SchemaInfo, SchemaKind, SchemaRegistry, SchemaVersion};
+pub use ricardian::{Error as RicardianError, RicardianContract, CONTRACT_TEXT_MAX_LEN};
+pub use rules::Error as RuleError;
 pub use schema::{
-    schema, subschema, FieldType, OwnedRightType, SCHEMA_ID_BECH32, SUBSCHEMA_ID_BECH32,
+    schema, subschema, FieldType, OwnedRightType, SCHEMA_ID_BECH32, SCHEMA_V1_ID_BECH32,
+    SUBSCHEMA_ID_BECH32, SUBSCHEMA_V1_ID_BECH32,
+};
+pub use selection::{CoinSelection, Error as SelectionError, SelectionStrategy};
+pub use stash::{Error as StashError, Stash};
+pub use transitions::Error as TransitionError;
+pub use validation::{
+    Failure as ValidationFailure, MemTxResolver, TxResolver, TxResolverError, ValidationReport,
 };
@@ -0,0 +1,305 @@
+//! Transfers spending transaction outputs, which hold allocations of several
+//! RGB121 contracts at once.
+//!
+//! When a transaction output is spent, every contract having allocations on
+//! it must get a state transition closing the seal, otherwise the allocations
+//! are lost. Contracts which are not transferred get "blank" transitions,
+//! moving all their allocations to the change seal.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::OutPoint;
+use rgb::{seal, ConsignmentType, ContractId, EndpointValueMap, InmemConsignment, Transition};
+
+use crate::{embed_transition, psbt, Asset, TransitionError};
+
+/// Errors happening during construction of multi-contract transfer
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// contract {0} is provided more than once
+    DuplicateContract(ContractId),
+
+    /// payment refers to contract {0}, which is not among the provided assets
+    UnknownContract(ContractId),
+
+    /// none of the provided assets has allocations on the spent transaction
+    /// outputs
+    NoAllocations,
+
+    /// unable to construct state transition for contract {contract_id}: {error}
+    Transition {
+        /// Contract for which the transition was constructed
+        contract_id: ContractId,
+        /// Transition construction error
+        error: TransitionError,
+    },
+}
+
+/// State transitions of several contracts spending the same transaction
+/// outputs, which must be committed by a single anchor
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MultiTransfer {
+    /// State transitions for each of the contracts having allocations on the
+    /// spent transaction outputs
+    pub transitions: BTreeMap<ContractId, Transition>,
+    /// Contracts with blank transitions, which only move the allocations to
+    /// the change seal
+    pub blank: BTreeSet<ContractId>,
+}
+
+impl MultiTransfer {
+    /// Records all state transitions in the PSBT with [`embed_transition`],
+    /// after which [`crate::complete_commitment`] produces a single anchor for
+    /// all of them. `consignments` must contain the history of each of the
+    /// transferred contracts.
+    pub fn embed<T>(
+        &self,
+        psbt: &mut PartiallySignedTransaction,
+        consignments: &[InmemConsignment<T>],
+    ) -> Result<(), psbt::Error>
+    where
+        T: ConsignmentType,
+    {
+        for (contract_id, transition) in &self.transitions {
+            let consignment = consignments
+                .iter()
+                .find(|consignment| consignment.contract_id() == *contract_id)
+                .ok_or(psbt::Error::UnknownContract(*contract_id))?;
+            embed_transition(psbt, consignment, transition.clone())?;
+        }
+        Ok(())
+    }
+}
+
+/// Constructs state transitions for all the `assets` having allocations on
+/// the spent `inputs`.
+///
+/// Contracts listed in `payments` transfer the specified amounts to the
+/// beneficiaries, returning the rest of the asset and all the engraving rights
+/// on the `inputs` to the `change_seal`; all other contracts get blank
+/// transitions (see [`Asset::blank_transition`]) moving all their allocations
+/// and engraving rights to the `change_seal`. Assets without allocations on
+/// the `inputs` are skipped. Allocations of confidential amount on the
+/// `inputs` can't be moved, so they fail the transfer with
+/// [`TransitionError::ConfidentialInput`].
+pub fn transfer_multi(
+    assets: &[Asset],
+    inputs: BTreeSet<OutPoint>,
+    mut payments: BTreeMap<ContractId, EndpointValueMap>,
+    change_seal: seal::Revealed,
+) -> Result<MultiTransfer, Error> {
+    let mut transfer = MultiTransfer {
+        transitions: bmap! {},
+        blank: bset! {},
+    };
+    let mut seen = BTreeSet::new();
+    for asset in assets {
+        let contract_id = asset.contract_id();
+        if !seen.insert(contract_id) {
+            return Err(Error::DuplicateContract(contract_id));
+        }
+        let map_err = |error| Error::Transition { contract_id, error };
+        let payment = match payments.remove(&contract_id) {
+            Some(payment) => payment,
+            None => {
+                if let Some(transition) = asset
+                    .blank_transition(inputs.clone(), change_seal)
+                    .map_err(map_err)?
+                {
+                    transfer.blank.insert(contract_id);
+                    transfer.transitions.insert(contract_id, transition);
+                }
+                continue;
+            }
+        };
+
+        if let Some(transition) = asset
+            .spend_outpoints(&inputs, payment, change_seal)
+            .map_err(map_err)?
+        {
+            transfer.transitions.insert(contract_id, transition);
+        }
+    }
+
+    if let Some(contract_id) = payments.into_keys().next() {
+        return Err(Error::UnknownContract(contract_id));
+    }
+    if transfer.transitions.is_empty() {
+        return Err(Error::NoAllocations);
+    }
+    Ok(transfer)
+}
+
+#[cfg(test)]
+mod test {
+    use rgb::{Contract, Node, Validity};
+    use seals::txout::CloseMethod;
+    use wallet::psbt::Psbt;
+
+    use super::*;
+    use crate::schema::{OwnedRightType, TransitionType};
+    use crate::test_helpers::{beneficiary, outpoint, seal, witness_tx, Issuance, TempFile};
+    use crate::{complete_commitment, finalize_transfer, MemTxResolver, ValidationReport};
+
+    fn contract(name: &'static str, allocations: &[(u32, u64)]) -> Contract {
+        Issuance {
+            name,
+            ..Issuance::with(allocations)
+        }
+        .contract()
+    }
+
+    #[test]
+    fn bundle() {
+        let contracts = vec![
+            contract("Target", &[(0, 5)]),
+            contract("Colocated", &[(0, 3), (1, 2)]),
+            contract("Unrelated", &[(2, 7)]),
+        ];
+        let assets = contracts
+            .iter()
+            .map(|contract| Asset::try_from(contract).unwrap())
+            .collect::<Vec<_>>();
+        let ids = assets.iter().map(Asset::contract_id).collect::<Vec<_>>();
+        let beneficiary = beneficiary(10);
+        let change = seal(11);
+
+        let transfer = transfer_multi(
+            &assets,
+            bset![outpoint(0)],
+            bmap! { ids[0] => bmap! { beneficiary => 4 } },
+            change,
+        )
+        .unwrap();
+        assert_eq!(
+            transfer
+                .transitions
+                .keys()
+                .copied()
+                .collect::<BTreeSet<_>>(),
+            bset![ids[0], ids[1]]
+        );
+        assert_eq!(transfer.blank, bset![ids[1]]);
+
+        let tx = witness_tx(outpoint(0));
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        transfer.embed(&mut psbt, &contracts).unwrap();
+        let anchor = complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+        for contract_id in transfer.transitions.keys() {
+            assert!(anchor.clone().into_merkle_proof(*contract_id).is_ok());
+        }
+
+        let resolver = MemTxResolver::with([Psbt::from(psbt.clone()).into_unsigned_tx()]);
+        for contract in &contracts[..2] {
+            let consignment = finalize_transfer(&psbt, contract).unwrap();
+            let report = ValidationReport::with(&consignment, &resolver);
+            assert_eq!(report.validity(), Validity::Valid, "{}", report);
+        }
+        assert_eq!(
+            finalize_transfer(&psbt, &contracts[2]).map(|_| ()),
+            Err(psbt::Error::UnknownContract(ids[2]))
+        );
+    }
+
+    #[test]
+    fn engraving() {
+        let file = TempFile::with("media");
+        let contract = Issuance {
+            name: "Target",
+            attachments: vec![file.attachment()],
+            ..Issuance::with(&[(0, 5)])
+        }
+        .contract();
+        let asset = Asset::try_from(&contract).unwrap();
+        let contract_id = asset.contract_id();
+        let beneficiary = beneficiary(10);
+        let change = seal(11);
+
+        let transfer = transfer_multi(
+            std::slice::from_ref(&asset),
+            bset![outpoint(0)],
+            bmap! { contract_id => bmap! { beneficiary => 4 } },
+            change,
+        )
+        .unwrap();
+        assert!(transfer.blank.is_empty());
+        let transition = &transfer.transitions[&contract_id];
+        assert_eq!(
+            transition.transition_type(),
+            TransitionType::Engraving as u16
+        );
+        assert_eq!(
+            transition
+                .owned_rights_by_type(OwnedRightType::Engraving.into())
+                .unwrap()
+                .revealed_seal_outputs(),
+            vec![(change, 0)]
+        );
+
+        let tx = witness_tx(outpoint(0));
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        transfer
+            .embed(&mut psbt, std::slice::from_ref(&contract))
+            .unwrap();
+        complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+        let resolver = MemTxResolver::with([Psbt::from(psbt.clone()).into_unsigned_tx()]);
+        let consignment = finalize_transfer(&psbt, &contract).unwrap();
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+    }
+
+    #[test]
+    fn failures() {
+        let contract = contract("Collectible", &[(0, 5)]);
+        let asset = Asset::try_from(&contract).unwrap();
+        let contract_id = asset.contract_id();
+        let change = seal(11);
+        let beneficiary = beneficiary(10);
+
+        assert_eq!(
+            transfer_multi(
+                &[asset.clone(), asset.clone()],
+                bset![outpoint(0)],
+                bmap! {},
+                change
+            ),
+            Err(Error::DuplicateContract(contract_id))
+        );
+        assert_eq!(
+            transfer_multi(
+                std::slice::from_ref(&asset),
+                bset![outpoint(1)],
+                bmap! {},
+                change
+            ),
+            Err(Error::NoAllocations)
+        );
+        assert_eq!(
+            transfer_multi(
+                &[],
+                bset![outpoint(0)],
+                bmap! { contract_id => bmap! {} },
+                change
+            ),
+            Err(Error::UnknownContract(contract_id))
+        );
+        assert_eq!(
+            transfer_multi(
+                &[asset],
+                bset![outpoint(0)],
+                bmap! { contract_id => bmap! { beneficiary => 6 } },
+                change
+            ),
+            Err(Error::Transition {
+                contract_id,
+                error: TransitionError::InputsNotEqualOutputs {
+                    inputs: 5,
+                    outputs: 6,
+                    per_input: bmap! { outpoint(0) => 5 }
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Provenance of RGB121 assets: ordered history of the asset contract from
+//! the genesis through all the state transitions known to a consignment.
+//!
+//! Unlike [`crate::Asset`], which keeps only the resulting contract state, the
+//! provenance preserves the graph of state transitions, linking each of the
+//! transition inputs to the outputs of the preceding nodes. The graph can be
+//! exported in Graphviz DOT format with [`Provenance::to_dot`].
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use bitcoin::{OutPoint, Txid};
+use rgb::{
+    AtomicValue, AttachmentId, Consignment, ConsignmentType, ContractId, InmemConsignment, Node,
+    NodeId, NodeOutpoint, TypedAssignments,
+};
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use crate::export::transition_name;
+use crate::stash::topological_order;
+use crate::{asset, OwnedRightType, SchemaRegistry};
+
+/// Asset amount assigned by a node of the contract history
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ProvenanceOutput {
+    /// Node output assigning the amount
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_outpoint: NodeOutpoint,
+    /// Transaction output receiving the amount, if the seal is not concealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<Option<DisplayFromStr>>"))]
+    pub seal: Option<OutPoint>,
+    /// Assigned amount, if it is not confidential
+    pub amount: Option<AtomicValue>,
+}
+
+/// Attachment (engraving) assigned by a node of the contract history
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ProvenanceEngraving {
+    /// Node output assigning the attachment
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_outpoint: NodeOutpoint,
+    /// Transaction output receiving the attachment, if the seal is not
+    /// concealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<Option<DisplayFromStr>>"))]
+    pub seal: Option<OutPoint>,
+    /// Attachment id, if the attachment is not concealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<Option<DisplayFromStr>>"))]
+    pub id: Option<AttachmentId>,
+    /// Attachment MIME type, if the attachment is not concealed
+    pub mime: Option<String>,
+}
+
+/// Genesis or state transition in the contract history
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ProvenanceRecord {
+    /// Node id, which for the genesis matches the contract id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_id: NodeId,
+    /// Name of the transition type, or `Genesis`
+    pub node_type: String,
+    /// Witness transaction id; absent for the genesis
+    #[cfg_attr(feature = "serde", serde(with = "As::<Option<DisplayFromStr>>"))]
+    pub witness_txid: Option<Txid>,
+    /// Outputs of the preceding nodes closed by the transition
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<DisplayFromStr>>"))]
+    pub inputs: Vec<NodeOutpoint>,
+    /// Asset amounts assigned by the node
+    pub outputs: Vec<ProvenanceOutput>,
+    /// Attachments assigned by the node
+    pub engravings: Vec<ProvenanceEngraving>,
+}
+
+/// Ordered history of RGB121 asset
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct Provenance {
+    /// Asset contract id
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub contract_id: ContractId,
+    /// Genesis followed by the state transitions, each of which goes after
+    /// all the nodes it spends from
+    pub records: Vec<ProvenanceRecord>,
+}
+
+impl Provenance {
+    /// Extracts provenance of RGB121 asset from the consignment
+    pub fn with<T>(consignment: &InmemConsignment<T>) -> Result<Self, asset::Error>
+    where T: ConsignmentType {
+        if !SchemaRegistry::new().is_supported(consignment.schema_id()) {
+            return Err(asset::Error::WrongSchemaId);
+        }
+
+        let genesis = consignment.genesis();
+        let mut records = vec![record(genesis, s!("Genesis"), None)];
+        let bundles = consignment
+            .anchored_bundles()
+            .map(|(anchor, bundle)| (anchor.clone(), bundle.clone()))
+            .collect();
+        for (anchor, bundle) in topological_order(bundles) {
+            let mut transitions = bundle.known_transitions().collect::<Vec<_>>();
+            transitions.sort_by_key(|transition| transition.node_id());
+            for transition in transitions {
+                let name = transition_name(transition.transition_type());
+                records.push(record(transition, name, Some(anchor.txid)));
+            }
+        }
+
+        Ok(Provenance {
+            contract_id: consignment.contract_id(),
+            records,
+        })
+    }
+
+    /// Renders the history as a Graphviz DOT directed graph, where edges link
+    /// node outputs with the transitions closing them, and the outputs which
+    /// are not closed by any of the known transitions point to their seals
+    pub fn to_dot(&self) -> String {
+        let mut labels = BTreeMap::<NodeOutpoint, String>::new();
+        for record in &self.records {
+            for output in &record.outputs {
+                let amount = output
+                    .amount
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_else(|| s!("?"));
+                labels.insert(output.node_outpoint, amount);
+            }
+            for engraving in &record.engravings {
+                let id = engraving
+                    .id
+                    .map(|id| short_id(&id.to_string()))
+                    .unwrap_or_else(|| s!("?"));
+                labels.insert(engraving.node_outpoint, format!("engraving {}", id));
+            }
+        }
+        let mut unspent = labels.clone();
+
+        let mut dot = format!("digraph \"{}\" {{\n    rankdir=LR;\n", self.contract_id);
+        for record in &self.records {
+            let node_id = record.node_id.to_string();
+            writeln!(
+                dot,
+                "    \"{}\" [shape=box, label=\"{}\\n{}\"];",
+                node_id,
+                record.node_type,
+                short_id(&node_id)
+            )
+            .expect("writing to string");
+            for input in &record.inputs {
+                unspent.remove(input);
+                let label = labels.get(input).map(String::as_str).unwrap_or("?");
+                writeln!(
+                    dot,
+                    "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                    input.node_id, node_id, label
+                )
+                .expect("writing to string");
+            }
+        }
+        let seals = self
+            .records
+            .iter()
+            .flat_map(|record| {
+                record
+                    .outputs
+                    .iter()
+                    .map(|output| (output.node_outpoint, output.seal))
+                    .chain(
+                        record
+                            .engravings
+                            .iter()
+                            .map(|engraving| (engraving.node_outpoint, engraving.seal)),
+                    )
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (node_outpoint, label) in unspent {
+            let seal = seals
+                .get(&node_outpoint)
+                .copied()
+                .flatten()
+                .map(|seal| seal.to_string())
+                .unwrap_or_else(|| s!("concealed seal"));
+            writeln!(
+                dot,
+                "    \"{}\" [shape=ellipse, label=\"{}\"];\n    \"{}\" -> \"{}\" [label=\"{}\"];",
+                node_outpoint, seal, node_outpoint.node_id, node_outpoint, label
+            )
+            .expect("writing to string");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn record(node: &dyn Node, node_type: String, witness_txid: Option<Txid>) -> ProvenanceRecord {
+    let node_id = node.node_id();
+    let seal_outpoint = |seal: Option<rgb::seal::Revealed>| {
+        seal.and_then(|seal| {
+            seal.txid
+                .or(witness_txid)
+                .map(|txid| OutPoint::new(txid, seal.vout))
+        })
+    };
+    let mut outputs = vec![];
+    let mut engravings = vec![];
+    for (ty, assignments) in node.owned_rights().iter() {
+        match assignments {
+            // Inflation rights are not asset allocations
+            TypedAssignments::Value(_) if *ty != OwnedRightType::Assets as u16 => {}
+            TypedAssignments::Value(assignments) => {
+                for (no, assignment) in assignments.iter().enumerate() {
+                    outputs.push(ProvenanceOutput {
+                        node_outpoint: NodeOutpoint::new(node_id, *ty, no as u16),
+                        seal: seal_outpoint(assignment.revealed_seal()),
+                        amount: assignment.as_revealed_state().map(|state| state.value),
+                    });
+                }
+            }
+            TypedAssignments::Attachment(assignments) => {
+                for (no, assignment) in assignments.iter().enumerate() {
+                    let state = assignment.as_revealed_state();
+                    engravings.push(ProvenanceEngraving {
+                        node_outpoint: NodeOutpoint::new(node_id, *ty, no as u16),
+                        seal: seal_outpoint(assignment.revealed_seal()),
+                        id: state.map(|state| state.id),
+                        mime: state.map(|state| state.mime.to_string()),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    ProvenanceRecord {
+        node_id,
+        node_type,
+        witness_txid,
+        inputs: node.parent_outputs(),
+        outputs,
+        engravings,
+    }
+}
+
+fn short_id(id: &str) -> String { id.chars().take(8).collect() }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use rgb::seal;
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::test_helpers::{beneficiary, contract, outpoint, witness_tx};
+    use crate::{
+        complete_commitment, embed_transition, finalize_transfer, Asset, ConsignmentDetails,
+    };
+
+    #[test]
+    fn history() {
+        let contract = contract(&[(0, 5)]);
+        let provenance = Provenance::with(&contract).unwrap();
+        assert_eq!(provenance.records.len(), 1);
+        assert_eq!(provenance.records[0].node_type, "Genesis");
+        assert_eq!(provenance.records[0].outputs[0].amount, Some(5));
+
+        let asset = Asset::try_from(&contract).unwrap();
+        let beneficiary = beneficiary(1);
+        let change = seal::Revealed {
+            method: CloseMethod::OpretFirst,
+            txid: None,
+            vout: 0,
+            blinding: 1,
+        };
+        let transition = asset
+            .transfer(
+                bset![outpoint(0)],
+                bmap! { beneficiary => 2 },
+                bmap! { change => 3 },
+            )
+            .unwrap();
+        let tx = witness_tx(outpoint(0));
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        embed_transition(&mut psbt, &contract, transition.clone()).unwrap();
+        complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+        let witness_txid = psbt.unsigned_tx.txid();
+        let transfer = finalize_transfer(&psbt, &contract).unwrap();
+
+        let provenance = Provenance::with(&transfer).unwrap();
+        assert_eq!(provenance.contract_id, contract.contract_id());
+        assert_eq!(provenance.records.len(), 2);
+        let genesis = &provenance.records[0];
+        let record = &provenance.records[1];
+        assert_eq!(record.node_id, transition.node_id());
+        assert_eq!(record.node_type, "Transfer");
+        assert_eq!(record.witness_txid, Some(witness_txid));
+        assert_eq!(record.inputs, vec![genesis.outputs[0].node_outpoint]);
+        let mut outputs = record
+            .outputs
+            .iter()
+            .map(|output| (output.seal, output.amount))
+            .collect::<Vec<_>>();
+        outputs.sort();
+        assert_eq!(outputs, vec![
+            (None, Some(2)),
+            (Some(OutPoint::new(witness_txid, 0)), Some(3))
+        ]);
+        assert!(record.engravings.is_empty());
+
+        let details = ConsignmentDetails::with(&transfer).unwrap();
+        assert_eq!(details.history.len(), 1);
+        assert_eq!(details.history[0].node_id, record.node_id);
+        assert_eq!(details.history[0].witness_txid, witness_txid);
+        assert_eq!(details.history[0].inputs, record.inputs);
+        assert_eq!(details.history[0].outputs, 2);
+
+        let dot = provenance.to_dot();
+        assert!(dot.starts_with(&format!("digraph \"{}\"", contract.contract_id())));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [label=\"5\"];",
+            genesis.node_id, record.node_id
+        )));
+        assert!(dot.contains("concealed seal"));
+        assert!(dot.contains(&format!("{}:0", witness_txid)));
+    }
+}
@@ -0,0 +1,558 @@
+//! Embedding of RGB121 state transitions into bitcoin PSBTs and completion of
+//! the deterministic bitcoin commitments (tapret or opret) to them.
+//!
+//! The workflow is the following:
+//! 1. the wallet constructs PSBT spending all bitcoin transaction outputs
+//!    holding the assets which are spent by the state transition;
+//! 2. [`embed_transition`] records the state transition in the PSBT
+//!    proprietary keys together with the information which inputs are
+//!    consumed by it; [`embed_blank_transitions`] then adds blank transitions
+//!    for all other contracts having allocations on the spent outputs;
+//! 3. [`complete_commitment`] commits to all the embedded transitions with
+//!    either tapret or opret commitment, producing the anchor;
+//! 4. once the PSBT is signed, [`finalize_transfer`] extracts the anchor from
+//!    it and composes the state transfer consignment for the beneficiary.
+
+use std::collections::BTreeSet;
+
+use amplify::Wrapper;
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::{OutPoint, Script, TxOut};
+use bitcoin_scripts::taproot::DfsPath;
+use commit_verify::lnpbp4::{self, MerkleBlock};
+use commit_verify::{ConsensusCommit, TaggedHash};
+use dbc::tapret::TapretProof;
+use rgb::psbt::{KeyError, RgbExt, RgbInExt};
+use rgb::{
+    seal, Anchor, Consignment, ConsignmentType, Contract, ContractId, InmemConsignment, Node,
+    NodeOutpoint, StateTransfer, Transition,
+};
+use seals::txout::CloseMethod;
+use strict_encoding::{StrictDecode, StrictEncode};
+use wallet::psbt::commit::{Lnpbp4KeyError, OpretKeyError, TapretKeyError};
+use wallet::psbt::{Output, Psbt};
+
+use crate::{asset, compose, compose_transfer, Asset, TransitionError};
+
+/// PSBT proprietary key prefix used for RGB121-specific data.
+pub const PSBT_RGB121_PREFIX: &[u8] = b"RGB121";
+/// Proprietary key subtype for storing LNPBP4 merkle block of the completed
+/// commitment in the map of the commitment host output.
+pub const PSBT_OUT_LNPBP4_BLOCK: u8 = 0x00;
+
+/// Errors happening during embedding of RGB121 state transitions into PSBT
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// invalid RGB121 consignment: {0}
+    #[from]
+    Asset(asset::Error),
+
+    /// state transition spends {0}, which is not known to the consignment
+    UnknownInput(NodeOutpoint),
+
+    /// PSBT does not spend transaction output {0}, which is closed by the
+    /// state transition
+    MissingInput(OutPoint),
+
+    /// PSBT does not have output #{0}
+    NoOutput(u32),
+
+    /// output #{0} can't host tapret commitment since it is not a taproot
+    /// output with known internal key
+    NotTaprootOutput(u32),
+
+    /// PSBT does not contain any RGB state transitions
+    NoTransitions,
+
+    /// invalid RGB data in PSBT proprietary keys: {0}
+    #[from]
+    Key(KeyError),
+
+    /// invalid opret commitment data in PSBT: {0}
+    #[from]
+    Opret(OpretKeyError),
+
+    /// invalid tapret commitment data in PSBT: {0}
+    #[from]
+    Tapret(TapretKeyError),
+
+    /// unable to commit to the state transitions: {0}
+    #[from]
+    Commitment(dbc::anchor::Error),
+
+    /// unsupported seal closing method {0}
+    UnsupportedMethod(CloseMethod),
+
+    /// PSBT does not contain completed tapret or opret commitment
+    NoCommitment,
+
+    /// invalid LNPBP4 data in PSBT proprietary keys: {0}
+    #[from]
+    Lnpbp4(Lnpbp4KeyError),
+
+    /// LNPBP4 data in PSBT proprietary keys do not match the commitment
+    InvalidCommitment,
+
+    /// PSBT already has `OP_RETURN` output #{0}, which can't host opret
+    /// commitment
+    OpReturnPresent(u32),
+
+    /// anchor does not commit to the state transitions of contract {0}
+    UnknownContract(ContractId),
+
+    /// PSBT does not contain state transitions of contract {0}
+    NoBundle(ContractId),
+
+    /// unable to compose state transfer: {0}
+    #[from]
+    Compose(compose::Error),
+
+    /// unable to construct blank state transition: {0}
+    #[from]
+    Transition(TransitionError),
+}
+
+/// Records RGB121 state transition in the PSBT proprietary keys, marking all
+/// the PSBT inputs which spend the transaction outputs closed by the
+/// transition. The `consignment` must contain the history of all the spent
+/// assets.
+///
+/// The function may be called multiple times for different state transitions
+/// (including transitions of different contracts) before the commitment is
+/// completed with [`complete_commitment`].
+pub fn embed_transition<T>(
+    psbt: &mut PartiallySignedTransaction,
+    consignment: &InmemConsignment<T>,
+    transition: Transition,
+) -> Result<(), Error>
+where
+    T: ConsignmentType,
+{
+    let asset = Asset::try_from(consignment)?;
+    let contract_id = consignment.contract_id();
+    let node_id = transition.node_id();
+
+    let mut wallet_psbt = Psbt::from(psbt.clone());
+    for (parent_id, rights) in transition.parent_owned_rights().iter() {
+        for (ty, indexes) in rights {
+            for no in indexes {
+                let node_outpoint = NodeOutpoint::new(*parent_id, *ty, *no);
+                let seal = seal_outpoint(&asset, node_outpoint)
+                    .ok_or(Error::UnknownInput(node_outpoint))?;
+                let input = wallet_psbt
+                    .inputs
+                    .iter_mut()
+                    .find(|input| input.previous_outpoint == seal)
+                    .ok_or(Error::MissingInput(seal))?;
+                input.set_rgb_consumer(contract_id, node_id)?;
+            }
+        }
+    }
+
+    if !wallet_psbt.has_rgb_contract(contract_id) {
+        let contract = Contract::with(
+            consignment.schema().clone(),
+            consignment.root_schema().cloned(),
+            consignment.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        wallet_psbt.set_rgb_contract(contract)?;
+    }
+    wallet_psbt.push_rgb_transition(transition)?;
+
+    *psbt = wallet_psbt.into();
+    Ok(())
+}
+
+/// Protects asset allocations on the transaction outputs spent by the PSBT
+/// from being lost, embedding blank transitions (see
+/// [`Asset::blank_transition`]) which move them to the `new_seal`.
+///
+/// Contracts from `consignments`, which already have state transitions
+/// embedded into the PSBT, are skipped, so the function should be called
+/// after all the transfers are embedded and before the commitment is
+/// completed. Returns ids of the contracts which got blank transitions.
+pub fn embed_blank_transitions<T>(
+    psbt: &mut PartiallySignedTransaction,
+    consignments: &[InmemConsignment<T>],
+    new_seal: seal::Revealed,
+) -> Result<BTreeSet<ContractId>, Error>
+where
+    T: ConsignmentType,
+{
+    let spent = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .collect::<BTreeSet<_>>();
+    let mut blank = bset! {};
+    for consignment in consignments {
+        let contract_id = consignment.contract_id();
+        if Psbt::from(psbt.clone()).has_rgb_contract(contract_id) {
+            continue;
+        }
+        let asset = Asset::try_from(consignment)?;
+        if let Some(transition) = asset.blank_transition(spent.clone(), new_seal)? {
+            embed_transition(psbt, consignment, transition)?;
+            blank.insert(contract_id);
+        }
+    }
+    Ok(blank)
+}
+
+/// Completes deterministic bitcoin commitment to all the state transitions
+/// embedded into the PSBT, returning the anchor.
+///
+/// For [`CloseMethod::TapretFirst`] the commitment is put into the taproot
+/// output number `change_vout`, which must be owned by the wallet (usually
+/// it is the change output). For [`CloseMethod::OpretFirst`] the commitment
+/// is put into a new `OP_RETURN` output added to the transaction; the
+/// `change_vout` is ignored in this case. Since a transaction may have only a
+/// single `OP_RETURN` output, the function fails if the PSBT already has one
+/// which does not host opret commitment.
+///
+/// The LNPBP4 merkle block of the commitment is stored in the
+/// [`PSBT_OUT_LNPBP4_BLOCK`] proprietary key of the commitment host output,
+/// from which [`extract_anchor`] reads it.
+///
+/// Since the commitment modifies transaction outputs, the PSBT must be signed
+/// only after the commitment is completed.
+pub fn complete_commitment(
+    psbt: &mut PartiallySignedTransaction,
+    method: CloseMethod,
+    change_vout: u32,
+) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+    let mut wallet_psbt = Psbt::from(psbt.clone());
+    if wallet_psbt.rgb_contract_ids().is_empty() {
+        return Err(Error::NoTransitions);
+    }
+
+    match method {
+        CloseMethod::TapretFirst => {
+            let output = wallet_psbt
+                .outputs
+                .get_mut(change_vout as usize)
+                .ok_or(Error::NoOutput(change_vout))?;
+            if !output.script.as_inner().is_v1_p2tr() || output.tap_internal_key.is_none() {
+                return Err(Error::NotTaprootOutput(change_vout));
+            }
+            if !output.is_tapret_host() {
+                output.set_tapret_dfs_path(&DfsPath::default())?;
+            }
+        }
+        CloseMethod::OpretFirst => {
+            match wallet_psbt
+                .outputs
+                .iter_mut()
+                .find(|output| output.script.as_inner().is_op_return())
+            {
+                Some(output) if output.is_opret_host() => output,
+                Some(output) => return Err(Error::OpReturnPresent(output.index() as u32)),
+                None => {
+                    let output = Output::new(wallet_psbt.outputs.len(), TxOut {
+                        value: 0,
+                        script_pubkey: Script::new_op_return(&[0u8; 32]),
+                    });
+                    wallet_psbt.outputs.push(output);
+                    wallet_psbt.outputs.last_mut().expect("just inserted")
+                }
+            }
+            .set_opret_host()?;
+        }
+        #[allow(unreachable_patterns)]
+        method => return Err(Error::UnsupportedMethod(method)),
+    }
+
+    wallet_psbt.rgb_bundle_to_lnpbp4()?;
+    let anchor = Anchor::commit(&mut wallet_psbt)?;
+    let host = wallet_psbt
+        .outputs
+        .iter_mut()
+        .find(|output| output.is_tapret_host() || output.is_opret_host())
+        .expect("commitment host is set above");
+    host.proprietary.insert(
+        lnpbp4_block_key(),
+        anchor
+            .lnpbp4_proof
+            .strict_serialize()
+            .expect("memory encoder does not fail"),
+    );
+
+    *psbt = wallet_psbt.into();
+    Ok(anchor)
+}
+
+/// Extracts the anchor from the PSBT, in which the commitment was completed
+/// with [`complete_commitment`]. The PSBT may be already signed or finalized.
+pub fn extract_anchor(
+    psbt: &PartiallySignedTransaction,
+) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+    let wallet_psbt = Psbt::from(psbt.clone());
+    let (output, commitment, dbc_proof) = if let Some(output) = wallet_psbt
+        .outputs
+        .iter()
+        .find(|output| output.is_tapret_host())
+    {
+        let commitment = output.tapret_commitment().ok_or(Error::NoCommitment)?;
+        let proof = output
+            .tapret_proof::<TapretProof>()?
+            .ok_or(Error::NoCommitment)?;
+        (output, commitment, dbc::Proof::TapretFirst(proof))
+    } else if let Some(output) = wallet_psbt
+        .outputs
+        .iter()
+        .find(|output| output.is_opret_host())
+    {
+        let commitment = output.opret_commitment()?.ok_or(Error::NoCommitment)?;
+        (output, commitment, dbc::Proof::OpretFirst)
+    } else {
+        return Err(Error::NoCommitment);
+    };
+
+    let block = output
+        .proprietary
+        .get(&lnpbp4_block_key())
+        .ok_or(Error::NoCommitment)?;
+    let block = MerkleBlock::strict_deserialize(block).map_err(|_| Error::InvalidCommitment)?;
+    if block.consensus_commit().into_array() != commitment.into_inner() {
+        return Err(Error::InvalidCommitment);
+    }
+
+    Ok(Anchor {
+        txid: wallet_psbt.to_txid(),
+        lnpbp4_proof: block,
+        dbc_proof,
+    })
+}
+
+/// Extracts the anchor from the signed PSBT and composes state transfer
+/// consignment for the beneficiary of the contract state transitions
+/// embedded into the PSBT. See [`compose_transfer`] for the details on the
+/// consignment content.
+pub fn finalize_transfer<T>(
+    psbt: &PartiallySignedTransaction,
+    source: &InmemConsignment<T>,
+) -> Result<StateTransfer, Error>
+where
+    T: ConsignmentType,
+{
+    let contract_id = source.contract_id();
+    let anchor = extract_anchor(psbt)?
+        .into_merkle_proof(contract_id)
+        .map_err(|_| Error::UnknownContract(contract_id))?;
+    let bundle = Psbt::from(psbt.clone())
+        .rgb_bundles()?
+        .remove(&contract_id)
+        .ok_or(Error::NoBundle(contract_id))?;
+    Ok(compose_transfer(source, anchor, bundle)?)
+}
+
+/// Constructs [`PSBT_OUT_LNPBP4_BLOCK`] proprietary key
+fn lnpbp4_block_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_RGB121_PREFIX.to_vec(),
+        subtype: PSBT_OUT_LNPBP4_BLOCK,
+        key: vec![],
+    }
+}
+
+/// Finds transaction output holding the asset state assigned by the node
+/// output
+fn seal_outpoint(asset: &Asset, node_outpoint: NodeOutpoint) -> Option<OutPoint> {
+    asset
+        .known_coins()
+        .chain(asset.known_inflation())
+        .find(|coin| coin.outpoint == node_outpoint)
+        .map(|coin| coin.seal)
+        .or_else(|| {
+            asset
+                .known_attachments()
+                .find(|attachment| attachment.outpoint == node_outpoint)
+                .map(|attachment| attachment.seal)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{XOnlyPublicKey, SECP256K1};
+    use bitcoin::util::taproot::TapBranchHash;
+    use bitcoin::{PackedLockTime, Sequence, Transaction, TxIn, WPubkeyHash, Witness};
+    use commit_verify::CommitConceal;
+    use rgb::{RevealSeals, SealEndpoint, Validity};
+
+    use super::*;
+    use crate::test_helpers::{outpoint, Issuance, TempFile};
+    use crate::{MemTxResolver, ValidationReport};
+
+    fn contract(method: CloseMethod) -> Contract {
+        Issuance {
+            method,
+            ..Issuance::with(&[(0, 5)])
+        }
+        .contract()
+    }
+
+    fn internal_key() -> XOnlyPublicKey {
+        XOnlyPublicKey::from_str("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap()
+    }
+
+    fn psbt(input: OutPoint, outputs: Vec<TxOut>) -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: input,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: outputs,
+        };
+        PartiallySignedTransaction::from_unsigned_tx(tx).unwrap()
+    }
+
+    /// Runs the complete workflow and validates the resulting state transfer
+    fn transfer(method: CloseMethod, mut psbt: PartiallySignedTransaction, change_vout: u32) {
+        let contract = contract(method);
+        let asset = Asset::try_from(&contract).unwrap();
+        let beneficiary =
+            SealEndpoint::ConcealedUtxo(seal::Revealed::new(method, outpoint(1)).commit_conceal());
+        let transition = asset
+            .transfer(bset![outpoint(0)], bmap! { beneficiary => 5 }, bmap! {})
+            .unwrap();
+
+        embed_transition(&mut psbt, &contract, transition).unwrap();
+        let anchor = complete_commitment(&mut psbt, method, change_vout).unwrap();
+        assert_eq!(extract_anchor(&psbt), Ok(anchor));
+
+        let consignment = finalize_transfer(&psbt, &contract).unwrap();
+        assert_eq!(
+            consignment
+                .endpoints()
+                .map(|(_, seal)| *seal)
+                .collect::<Vec<_>>(),
+            vec![beneficiary]
+        );
+
+        let tx = Psbt::from(psbt).into_unsigned_tx();
+        let resolver = MemTxResolver::with([tx]);
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+    }
+
+    #[test]
+    fn opret() {
+        let psbt = psbt(outpoint(0), vec![TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+        }]);
+        transfer(CloseMethod::OpretFirst, psbt, 0);
+    }
+
+    #[test]
+    fn tapret() {
+        let script_pubkey = Script::new_v1_p2tr(SECP256K1, internal_key(), None::<TapBranchHash>);
+        let mut psbt = psbt(outpoint(0), vec![TxOut {
+            value: 1000,
+            script_pubkey,
+        }]);
+        psbt.outputs[0].tap_internal_key = Some(internal_key());
+        transfer(CloseMethod::TapretFirst, psbt, 0);
+    }
+
+    #[test]
+    fn blank() {
+        let target = contract(CloseMethod::OpretFirst);
+        let file = TempFile::with("blank");
+        let colocated = Issuance {
+            name: "Colocated",
+            attachments: vec![file.attachment()],
+            ..Issuance::with(&[(0, 2)])
+        }
+        .contract();
+        let colocated_id = colocated.contract_id();
+        let asset = Asset::try_from(&target).unwrap();
+        let transition = asset
+            .transfer(bset![outpoint(0)], bmap! {}, bmap! {
+                seal::Revealed::new(CloseMethod::OpretFirst, outpoint(1)) => 5
+            })
+            .unwrap();
+
+        let mut psbt = psbt(outpoint(0), vec![TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+        }]);
+        embed_transition(&mut psbt, &target, transition).unwrap();
+        let change = seal::Revealed::new(CloseMethod::OpretFirst, outpoint(2));
+        let consignments = [target, colocated];
+        assert_eq!(
+            embed_blank_transitions(&mut psbt, &consignments, change),
+            Ok(bset![colocated_id])
+        );
+        complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+
+        let mut consignment = finalize_transfer(&psbt, &consignments[1]).unwrap();
+        consignment.reveal_seals(&[change]);
+        let tx = Psbt::from(psbt).into_unsigned_tx();
+        let resolver = MemTxResolver::with([tx]);
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+
+        let asset = Asset::try_from(&consignment).unwrap();
+        assert_eq!(asset.outpoint_coins(outpoint(2))[0].state.value, 2);
+        assert_eq!(asset.known_attachments().next().unwrap().seal, outpoint(2));
+    }
+
+    #[test]
+    fn errors() {
+        let contract = contract(CloseMethod::OpretFirst);
+        let asset = Asset::try_from(&contract).unwrap();
+        let transition = asset
+            .transfer(bset![outpoint(0)], bmap! {}, bmap! {
+                seal::Revealed::new(CloseMethod::OpretFirst, outpoint(1)) => 5
+            })
+            .unwrap();
+        let output = TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+        };
+
+        let mut wrong = psbt(outpoint(2), vec![output.clone()]);
+        assert_eq!(
+            embed_transition(&mut wrong, &contract, transition.clone()),
+            Err(Error::MissingInput(outpoint(0)))
+        );
+        assert_eq!(
+            complete_commitment(&mut wrong, CloseMethod::OpretFirst, 0),
+            Err(Error::NoTransitions)
+        );
+        assert_eq!(extract_anchor(&wrong), Err(Error::NoCommitment));
+
+        let mut psbt = psbt(outpoint(0), vec![output, TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(b"memo"),
+        }]);
+        embed_transition(&mut psbt, &contract, transition).unwrap();
+        assert_eq!(
+            complete_commitment(&mut psbt.clone(), CloseMethod::OpretFirst, 0),
+            Err(Error::OpReturnPresent(1))
+        );
+        assert_eq!(
+            complete_commitment(&mut psbt.clone(), CloseMethod::TapretFirst, 2),
+            Err(Error::NoOutput(2))
+        );
+        assert_eq!(
+            complete_commitment(&mut psbt, CloseMethod::TapretFirst, 0),
+            Err(Error::NotTaprootOutput(0))
+        );
+    }
+}
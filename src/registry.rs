@@ -0,0 +1,170 @@
+//! Registry of all RGB121 schema versions, allowing to work with assets issued
+//! under historical versions of the schemata.
+
+use std::str::FromStr;
+
+use rgb::schema::{Schema, SchemaId};
+
+use crate::schema::{
+    root_schema, sub_schema, SCHEMA_ID_BECH32, SCHEMA_V1_ID_BECH32, SUBSCHEMA_ID_BECH32,
+    SUBSCHEMA_V1_ID_BECH32,
+};
+
+/// Versions of RGB121 schemata
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[non_exhaustive]
+pub enum SchemaVersion {
+    /// Initial version of RGB121 schemata, released with RGB v0.9. Defines no
+    /// inflation rights, thus its assets can't be validly inflated
+    #[display("v1")]
+    V1,
+
+    /// Version of RGB121 schemata adding inflation rights closed by the
+    /// secondary issue
+    #[display("v2")]
+    V2,
+}
+
+impl SchemaVersion {
+    /// Latest (current) version of RGB121 schemata, returned by
+    /// [`crate::schema()`] and [`crate::subschema()`]
+    pub const LATEST: SchemaVersion = SchemaVersion::V2;
+
+    /// Detects whether schemata of this version define inflation rights,
+    /// controlling secondary issue of the asset
+    pub fn has_inflation_rights(self) -> bool { self >= SchemaVersion::V2 }
+}
+
+/// Kind of RGB121 schema: the root schema or its subschema
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum SchemaKind {
+    /// Root RGB121 schema, allowing all asset operations
+    #[display("schema")]
+    Root,
+
+    /// RGB121 subschema prohibiting engraving operation
+    #[display("subschema")]
+    Subschema,
+}
+
+/// Information about one of the known RGB121 schemata
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SchemaInfo {
+    /// Schema version
+    pub version: SchemaVersion,
+    /// Whether the schema is the root schema or a subschema
+    pub kind: SchemaKind,
+    /// Schema id
+    pub schema_id: SchemaId,
+}
+
+impl SchemaInfo {
+    /// Constructs the schema
+    pub fn schema(&self) -> Schema {
+        match self.kind {
+            SchemaKind::Root => root_schema(self.version),
+            SchemaKind::Subschema => sub_schema(self.version),
+        }
+    }
+}
+
+/// All known RGB121 schemata. Ids are hardcoded, such that a change in the
+/// schema definition is not able to silently change the id of an already
+/// released version.
+const KNOWN_SCHEMATA: [(SchemaVersion, SchemaKind, &str); 4] = [
+    (SchemaVersion::V1, SchemaKind::Root, SCHEMA_V1_ID_BECH32),
+    (
+        SchemaVersion::V1,
+        SchemaKind::Subschema,
+        SUBSCHEMA_V1_ID_BECH32,
+    ),
+    (SchemaVersion::V2, SchemaKind::Root, SCHEMA_ID_BECH32),
+    (
+        SchemaVersion::V2,
+        SchemaKind::Subschema,
+        SUBSCHEMA_ID_BECH32,
+    ),
+];
+
+/// Registry of all historical versions of RGB121 schemata
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SchemaRegistry(Vec<SchemaInfo>);
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        SchemaRegistry(
+            KNOWN_SCHEMATA
+                .iter()
+                .map(|(version, kind, id)| SchemaInfo {
+                    version: *version,
+                    kind: *kind,
+                    schema_id: SchemaId::from_str(id).expect("broken RGB121 schema id"),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl SchemaRegistry {
+    /// Constructs registry of all known RGB121 schemata
+    pub fn new() -> Self { Self::default() }
+
+    /// Iterates over all known RGB121 schemata
+    pub fn iter(&self) -> impl Iterator<Item = &SchemaInfo> { self.0.iter() }
+
+    /// Returns information about the schema with the given id, if it is one of
+    /// RGB121 schemata
+    pub fn info(&self, schema_id: SchemaId) -> Option<&SchemaInfo> {
+        self.0.iter().find(|info| info.schema_id == schema_id)
+    }
+
+    /// Returns version of RGB121 schema with the given id, if it is known
+    pub fn version(&self, schema_id: SchemaId) -> Option<SchemaVersion> {
+        self.info(schema_id).map(|info| info.version)
+    }
+
+    /// Detects whether the schema id belongs to one of RGB121 schemata
+    pub fn is_supported(&self, schema_id: SchemaId) -> bool { self.info(schema_id).is_some() }
+
+    /// Returns schema of a specific version and kind
+    pub fn schema(&self, version: SchemaVersion, kind: SchemaKind) -> Option<Schema> {
+        self.0
+            .iter()
+            .find(|info| info.version == version && info.kind == kind)
+            .map(SchemaInfo::schema)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{schema, subschema};
+
+    #[test]
+    fn known_ids() {
+        let registry = SchemaRegistry::new();
+        for info in registry.iter() {
+            assert_eq!(info.schema().schema_id(), info.schema_id);
+        }
+    }
+
+    #[test]
+    fn identify() {
+        let registry = SchemaRegistry::new();
+        let info = registry.info(schema().schema_id()).unwrap();
+        assert_eq!(info.version, SchemaVersion::LATEST);
+        assert_eq!(info.kind, SchemaKind::Root);
+        assert_eq!(
+            registry.version(subschema().schema_id()),
+            Some(SchemaVersion::V2)
+        );
+        assert_eq!(
+            registry.schema(SchemaVersion::V2, SchemaKind::Subschema),
+            Some(subschema())
+        );
+        let v1 = registry.info(SchemaId::from_str(SCHEMA_V1_ID_BECH32).unwrap());
+        assert_eq!(v1.map(|info| info.version), Some(SchemaVersion::V1));
+        assert!(!SchemaVersion::V1.has_inflation_rights());
+        assert!(!registry.is_supported(SchemaId::default()));
+    }
+}
@@ -0,0 +1,279 @@
+//! Ricardian contract text committed into RGB121 asset genesis.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256d, Hash};
+use stens::AsciiString;
+use url::Url;
+
+/// Maximal length of the contract text which can be embedded into the genesis
+pub const CONTRACT_TEXT_MAX_LEN: usize = u16::MAX as usize;
+
+/// Ricardian contract committed into the genesis with
+/// [`crate::FieldType::RicardianContract`] field.
+///
+/// Contract text may contain URL, text or text representation of Ricardian
+/// contract, up to 64kb. If the contract doesn't fit, a double SHA256 hash and
+/// URL should be used instead, pointing to the full contract text.
+///
+/// The hash is represented by a hexadecimal string, optionally followed by
+/// `\n` and text URL. Field values of any other form are read as the full
+/// contract text, thus texts looking like a hash can't be embedded.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum RicardianContract {
+    /// Full contract text embedded into the genesis
+    Text(AsciiString),
+
+    /// Double SHA256 hash of the contract text, optionally followed by the
+    /// URL pointing to the full contract text
+    Hash {
+        /// Double SHA256 hash of the contract text
+        hash: sha256d::Hash,
+        /// URL pointing to the full contract text
+        url: Option<Url>,
+    },
+}
+
+impl RicardianContract {
+    /// Constructs contract embedding its full text, failing if the text is
+    /// larger than 64kb or contains non-ASCII characters.
+    pub fn with_text(text: impl AsRef<str>) -> Result<Self, Error> {
+        let text = text.as_ref();
+        if text.len() > CONTRACT_TEXT_MAX_LEN {
+            return Err(Error::TooLong(text.len()));
+        }
+        if parse_hash(text).is_some() {
+            return Err(Error::HashLikeText);
+        }
+        AsciiString::from_str(text)
+            .map(RicardianContract::Text)
+            .map_err(|_| Error::NonAscii)
+    }
+
+    /// Constructs contract committing to the double SHA256 hash of the provided
+    /// text and pointing to its location with an optional URL.
+    pub fn with_hash(text: impl AsRef<[u8]>, url: Option<Url>) -> Self {
+        RicardianContract::Hash {
+            hash: sha256d::Hash::hash(text.as_ref()),
+            url,
+        }
+    }
+
+    /// Constructs contract embedding the full text when it fits the genesis
+    /// field, falling back to the hash of the text followed by the `url`
+    /// otherwise. Texts which would be read back as a hash are always
+    /// committed by their hash.
+    pub fn with(text: impl AsRef<str>, url: Option<Url>) -> Result<Self, Error> {
+        match Self::with_text(text.as_ref()) {
+            Err(Error::TooLong(len)) if url.is_none() => Err(Error::UrlRequired(len)),
+            Err(Error::TooLong(_)) | Err(Error::HashLikeText) => {
+                Ok(Self::with_hash(text.as_ref(), url))
+            }
+            res => res,
+        }
+    }
+
+    /// Returns full contract text, if it is embedded into the genesis
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            RicardianContract::Text(text) => Some(text.as_str()),
+            RicardianContract::Hash { .. } => None,
+        }
+    }
+
+    /// Returns URL pointing to the full contract text, if the contract is
+    /// committed by its hash and the URL is provided
+    pub fn url(&self) -> Option<&Url> {
+        match self {
+            RicardianContract::Text(_) => None,
+            RicardianContract::Hash { url, .. } => url.as_ref(),
+        }
+    }
+
+    /// Returns double SHA256 hash of the contract text
+    pub fn text_hash(&self) -> sha256d::Hash {
+        match self {
+            RicardianContract::Text(text) => sha256d::Hash::hash(text.as_bytes()),
+            RicardianContract::Hash { hash, .. } => *hash,
+        }
+    }
+
+    /// Checks that the provided text (for instance, downloaded from the
+    /// contract [`RicardianContract::url`]) matches the text committed into
+    /// the genesis.
+    pub fn verify_contract_text(&self, text: impl AsRef<[u8]>) -> Result<(), Error> {
+        let actual = sha256d::Hash::hash(text.as_ref());
+        let expected = self.text_hash();
+        if actual != expected {
+            return Err(Error::TextMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Returns representation of the contract suitable for storing in the
+    /// genesis [`crate::FieldType::RicardianContract`] field
+    pub fn to_ascii_string(&self) -> AsciiString {
+        AsciiString::from_str(&self.to_string())
+            .expect("RicardianContract string representation is always ASCII")
+    }
+}
+
+impl Display for RicardianContract {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RicardianContract::Text(text) => Display::fmt(text, f),
+            RicardianContract::Hash { hash, url: None } => Display::fmt(hash, f),
+            RicardianContract::Hash {
+                hash,
+                url: Some(url),
+            } => write!(f, "{}\n{}", hash, url),
+        }
+    }
+}
+
+impl FromStr for RicardianContract {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_hash(s) {
+            Some((hash, url)) => Ok(RicardianContract::Hash { hash, url }),
+            None => RicardianContract::with_text(s),
+        }
+    }
+}
+
+/// Parses field value of the hash form: a hexadecimal double SHA256 hash,
+/// optionally followed by `\n` and a valid URL
+fn parse_hash(s: &str) -> Option<(sha256d::Hash, Option<Url>)> {
+    let (hash, url) = match s.split_once('\n') {
+        Some((hash, url)) => (hash, Some(url)),
+        None => (s, None),
+    };
+    if hash.len() != 64 {
+        return None;
+    }
+    let hash = sha256d::Hash::from_hex(hash).ok()?;
+    let url = match url {
+        Some(url) => Some(Url::parse(url).ok()?),
+        None => None,
+    };
+    Some((hash, url))
+}
+
+/// Errors working with Ricardian contract data
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// contract text of {0} bytes does not fit into the genesis; a hash of the
+    /// text and URL must be used instead
+    TooLong(usize),
+
+    /// contract text of {0} bytes does not fit into the genesis and no URL
+    /// pointing to the full text is provided
+    UrlRequired(usize),
+
+    /// contract text must contain only ASCII characters
+    NonAscii,
+
+    /// contract text can't be embedded since it would be read as a hash of
+    /// the text; the text must be committed by its hash instead
+    HashLikeText,
+
+    /// provided contract text has hash {actual}, while the asset genesis
+    /// commits to {expected}
+    TextMismatch {
+        /// Hash of the contract text committed into the genesis
+        expected: sha256d::Hash,
+        /// Hash of the provided contract text
+        actual: sha256d::Hash,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_roundtrip() {
+        let contract = RicardianContract::with_text("Terms of the collectible").unwrap();
+        assert_eq!(contract.text(), Some("Terms of the collectible"));
+        assert_eq!(
+            RicardianContract::from_str(&contract.to_string()),
+            Ok(contract)
+        );
+    }
+
+    #[test]
+    fn hash_url_fallback() {
+        let text = "x".repeat(CONTRACT_TEXT_MAX_LEN + 1);
+        assert_eq!(
+            RicardianContract::with(&text, None),
+            Err(Error::UrlRequired(CONTRACT_TEXT_MAX_LEN + 1))
+        );
+
+        let url = Url::parse("https://example.com/contract.txt").unwrap();
+        let contract = RicardianContract::with(&text, Some(url.clone())).unwrap();
+        assert_eq!(contract.url(), Some(&url));
+        assert_eq!(contract.text(), None);
+        assert_eq!(
+            RicardianContract::from_str(&contract.to_string()),
+            Ok(contract.clone())
+        );
+
+        assert_eq!(contract.verify_contract_text(&text), Ok(()));
+        assert!(matches!(
+            contract.verify_contract_text("tampered"),
+            Err(Error::TextMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn hash_without_url() {
+        let contract = RicardianContract::with_hash("text", None);
+        let s = contract.to_string();
+        assert_eq!(s.len(), 64);
+        assert_eq!(RicardianContract::from_str(&s), Ok(contract));
+    }
+
+    #[test]
+    fn documented_form() {
+        let hash = sha256d::Hash::hash(b"text");
+        let url = Url::parse("https://example.com/contract.txt").unwrap();
+        assert_eq!(
+            RicardianContract::from_str(&format!("{}\n{}", hash, url)),
+            Ok(RicardianContract::Hash {
+                hash,
+                url: Some(url)
+            })
+        );
+        assert_eq!(
+            RicardianContract::from_str(&hash.to_string()),
+            Ok(RicardianContract::Hash { hash, url: None })
+        );
+
+        // Values not matching the hash form are read as the contract text
+        for text in [
+            format!("{}\nnot an url", hash),
+            format!("{}0", hash),
+            s!("https://example.com/contract.txt"),
+        ] {
+            let contract = RicardianContract::from_str(&text).unwrap();
+            assert_eq!(contract.text(), Some(text.as_str()));
+            assert_eq!(
+                RicardianContract::from_str(&contract.to_string()),
+                Ok(contract)
+            );
+        }
+
+        assert_eq!(
+            RicardianContract::with_text(hash.to_string()),
+            Err(Error::HashLikeText)
+        );
+        assert_eq!(
+            RicardianContract::with(hash.to_string(), None),
+            Ok(RicardianContract::with_hash(hash.to_string(), None))
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! RGB121 business rules complementing the embedded RGB validation script.
+//!
+//! RGB121 schemata use [`rgb::ValidationScript::Embedded`], which has no
+//! knowledge of RGB121-specific operations: it does not check that engraving
+//! preserves the amount of the asset, nor that the genesis and secondary issue
+//! allocate exactly the declared [`FieldType::IssuedSupply`]. It also checks
+//! only the number of inflation rights closed by the secondary issue, but not
+//! their allowance, and does not check that transfers moving inflation rights
+//! preserve it.
+//!
+//! These rules are **not** enforced at the consensus level: RGB Core v0.9
+//! embedded VM dispatches only on its built-in transition types, and its AluVM
+//! runtime does not provide node data to the scripts, so a schema can't
+//! express them. Thus [`rgb::Validator`] alone accepts consignments violating
+//! the rules, and a different RGB implementation may accept them as well.
+//! Wallets must check the rules off-chain, which is done for each node of a
+//! consignment during [`crate::ValidationReport`] construction.
+
+use commit_verify::CommitConceal;
+use rgb::schema::NodeSubtype;
+use rgb::secp256k1zkp::pedersen::Commitment;
+use rgb::{
+    secp256k1zkp, value, AtomicValue, Consignment, ConsignmentType, GraphApi, InmemConsignment,
+    Node, NodeId, TypedAssignments,
+};
+
+use crate::schema::{FieldType, OwnedRightType, TransitionType};
+use crate::transitions::checked_sum;
+
+/// Violations of RGB121 business rules
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// engraving transition {0} does not preserve the amount of the asset
+    EngravingAmountChanged(NodeId),
+
+    /// sum of the assets allocated by {0} does not match its issued supply
+    IssuedSupplyMismatch(NodeId),
+
+    /// issued supply declared by {0} exceeds the maximal asset amount
+    IssuedSupplyOverflow(NodeId),
+
+    /// secondary issue {0} together with the new inflation rights does not
+    /// match the allowance of the closed inflation rights
+    InflationMismatch(NodeId),
+
+    /// state transition {0} changes the allowance of the moved inflation
+    /// rights
+    InflationChanged(NodeId),
+}
+
+/// Checks RGB121 business rules for the genesis and all state transitions
+/// present in the consignment, returning the list of detected violations.
+pub(crate) fn validate<T>(consignment: &InmemConsignment<T>) -> Vec<Error>
+where T: ConsignmentType {
+    let mut errors = vec![];
+    let genesis = consignment.genesis() as &dyn Node;
+    let transitions = consignment
+        .anchored_bundles()
+        .flat_map(|(_, bundle)| bundle.revealed_iter().map(|(transition, _)| transition));
+    for node in std::iter::once(genesis).chain(transitions.map(|t| t as &dyn Node)) {
+        if let Err(err) = validate_node(consignment, node) {
+            errors.push(err);
+        }
+    }
+    errors
+}
+
+fn validate_node<T>(consignment: &InmemConsignment<T>, node: &dyn Node) -> Result<(), Error>
+where T: ConsignmentType {
+    let node_id = node.node_id();
+    let outputs = commitments(node.owned_rights_by_type(OwnedRightType::Assets.into()));
+    match node.subtype() {
+        NodeSubtype::Genesis => issue(node, outputs),
+        NodeSubtype::StateTransition(ty) if ty == TransitionType::Issue as u16 => {
+            issue(node, outputs)?;
+            inflation(consignment, node)
+        }
+        NodeSubtype::StateTransition(ty) if ty == TransitionType::Engraving as u16 => {
+            let inputs = parent_commitments(consignment, node, OwnedRightType::Assets);
+            if !value::Confidential::verify_commit_sum(outputs, inputs) {
+                return Err(Error::EngravingAmountChanged(node_id));
+            }
+            inflation_moved(consignment, node)
+        }
+        NodeSubtype::StateTransition(ty) if ty == TransitionType::Transfer as u16 => {
+            inflation_moved(consignment, node)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Verifies that the sum of assets allocated by the node matches
+/// [`FieldType::IssuedSupply`]. Since the assignments may be confidential, the
+/// check is performed on Pedersen commitments: the issued supply is committed
+/// with unit blinding factor, and the blinding factors of the allocations must
+/// sum up to one. This is the convention of RGB Core fungible issue validation
+/// (see `fungible_issue` in the RGB Core embedded VM), which RGB121 issue
+/// follows by balancing allocations against the unit-blinded supply (see
+/// [`crate::Rgb121::create_rgb121`] and [`crate::Asset::inflate`]). Issuers
+/// using any other blinding for the supply produce nodes which can't be
+/// verified without revealing all the allocated amounts.
+fn issue(node: &dyn Node, outputs: Vec<Commitment>) -> Result<(), Error> {
+    let issued = checked_sum(node.metadata().u64(FieldType::IssuedSupply))
+        .ok_or_else(|| Error::IssuedSupplyOverflow(node.node_id()))?;
+    if issued == 0 && outputs.is_empty() {
+        return Ok(());
+    }
+    let supply = value::Revealed {
+        value: issued,
+        blinding: secp256k1zkp::key::ONE_KEY.into(),
+    }
+    .commit_conceal()
+    .commitment;
+    if value::Confidential::verify_commit_sum(outputs, vec![supply]) {
+        Ok(())
+    } else {
+        Err(Error::IssuedSupplyMismatch(node.node_id()))
+    }
+}
+
+/// Verifies that the secondary issue together with the allowance of the new
+/// inflation rights spends exactly the allowance of the closed inflation
+/// rights. The allowance must be revealed, as it is required by RGB Core
+/// validation of the secondary issue.
+fn inflation<T>(consignment: &InmemConsignment<T>, node: &dyn Node) -> Result<(), Error>
+where T: ConsignmentType {
+    fn allowance(assignments: &TypedAssignments) -> Option<AtomicValue> {
+        let values = assignments.as_revealed_state_values().ok()?;
+        checked_sum(values.into_iter().map(|value| value.value))
+    }
+
+    let closed = allowance(&parent_assignments(
+        consignment,
+        node,
+        OwnedRightType::Inflation,
+    ));
+    let next = node
+        .owned_rights_by_type(OwnedRightType::Inflation.into())
+        .map_or(Some(0), allowance);
+    let issued = checked_sum(node.metadata().u64(FieldType::IssuedSupply));
+    match (closed, next, issued) {
+        (Some(closed), Some(next), Some(issued)) if issued.checked_add(next) == Some(closed) => {
+            Ok(())
+        }
+        _ => Err(Error::InflationMismatch(node.node_id())),
+    }
+}
+
+/// Verifies that the inflation rights moved by the transfer or engraving keep
+/// their total allowance
+fn inflation_moved<T>(consignment: &InmemConsignment<T>, node: &dyn Node) -> Result<(), Error>
+where T: ConsignmentType {
+    let inputs = parent_commitments(consignment, node, OwnedRightType::Inflation);
+    let outputs = commitments(node.owned_rights_by_type(OwnedRightType::Inflation.into()));
+    if (inputs.is_empty() && outputs.is_empty())
+        || value::Confidential::verify_commit_sum(outputs, inputs)
+    {
+        Ok(())
+    } else {
+        Err(Error::InflationChanged(node.node_id()))
+    }
+}
+
+/// Collects assignments of the given type closed by the node. Parent nodes
+/// absent from the consignment are skipped, since they are reported by the
+/// RGB core validator.
+fn parent_assignments<T>(
+    consignment: &InmemConsignment<T>,
+    node: &dyn Node,
+    ty: OwnedRightType,
+) -> TypedAssignments
+where
+    T: ConsignmentType,
+{
+    let mut assignments = vec![];
+    for (parent_id, rights) in node.parent_owned_rights().iter() {
+        let indexes = match rights.get(&ty.into()) {
+            Some(indexes) => indexes,
+            None => continue,
+        };
+        let parent = match consignment.node_by_id(*parent_id) {
+            Some(parent) => parent,
+            None => continue,
+        };
+        if let Some(TypedAssignments::Value(parent_assignments)) =
+            parent.owned_rights_by_type(ty.into())
+        {
+            assignments.extend(
+                indexes
+                    .iter()
+                    .filter_map(|index| parent_assignments.get(*index as usize))
+                    .cloned(),
+            );
+        }
+    }
+    TypedAssignments::Value(assignments)
+}
+
+/// Collects commitments to the amounts of the given type closed by the node
+fn parent_commitments<T>(
+    consignment: &InmemConsignment<T>,
+    node: &dyn Node,
+    ty: OwnedRightType,
+) -> Vec<Commitment>
+where
+    T: ConsignmentType,
+{
+    commitments(Some(&parent_assignments(consignment, node, ty)))
+}
+
+fn commitments(assignments: Option<&TypedAssignments>) -> Vec<Commitment> {
+    assignments
+        .map(TypedAssignments::to_confidential_state_pedersen)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|value| value.commitment)
+        .collect()
+}
@@ -1,5 +1,6 @@
 //! RGB121 schemata defining fungible asset smart contract prototypes.
 
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use rgb::schema::{
@@ -11,13 +12,25 @@ use rgb::vm::embedded::constants::*;
 use rgb::ValidationScript;
 use stens::{PrimitiveType, StructField, TypeRef, TypeSystem};
 
+use crate::SchemaVersion;
+
 /// Schema identifier for full RGB121 fungible asset
 pub const SCHEMA_ID_BECH32: &str =
-    "rgbsh1ykclt9qxkskqt88dwgccsp4w624k7adjwj06sknjkh04ygtc7rqsnykld7";
+    "rgbsh17290yrmpcyu6amd5ecte6jfzcfdpz73deakzlrav5pa2udv9dq6s05yanc";
 
 /// Schema identifier for full RGB121 fungible asset subschema prohibiting
 /// engraving operation
 pub const SUBSCHEMA_ID_BECH32: &str =
+    "rgbsh1pprvlwyvsnzw6vhg5utnp8776v3cvx08hu5ssf3l4gzpsws3tvrs4lm8a3";
+
+/// Schema identifier for full RGB121 fungible asset of
+/// [`SchemaVersion::V1`], which has no inflation rights
+pub const SCHEMA_V1_ID_BECH32: &str =
+    "rgbsh1ykclt9qxkskqt88dwgccsp4w624k7adjwj06sknjkh04ygtc7rqsnykld7";
+
+/// Schema identifier for full RGB121 fungible asset subschema of
+/// [`SchemaVersion::V1`], which has no inflation rights
+pub const SUBSCHEMA_V1_ID_BECH32: &str =
     "rgbsh1ep4k4qvghntwptcn0gqmfpdvr8vz3amslvy4pc7s32u7h500l5hqxlzjsk";
 
 /// Parent ID of the asset
@@ -84,6 +97,10 @@ pub enum OwnedRightType {
 
     /// Asset engraving right
     Engraving = STATE_TYPE_OWNERSHIP_RIGHT + 1,
+
+    /// Right to perform secondary issue of the asset, defined since
+    /// [`SchemaVersion::V2`]
+    Inflation = STATE_TYPE_INFLATION_RIGHT,
 }
 
 impl From<OwnedRightType> for rgb::schema::OwnedRightType {
@@ -123,9 +140,17 @@ fn type_system() -> TypeSystem {
     }
 }
 
-fn genesis() -> GenesisSchema {
+fn genesis(version: SchemaVersion) -> GenesisSchema {
     use Occurrences::*;
 
+    let mut owned_rights = type_map! {
+        OwnedRightType::Assets => NoneOrMore,
+        OwnedRightType::Engraving => NoneOrMore
+    };
+    if version.has_inflation_rights() {
+        owned_rights.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+    }
+
     GenesisSchema {
         metadata: type_map! {
             FieldType::Name => Once,
@@ -138,17 +163,27 @@ fn genesis() -> GenesisSchema {
             FieldType::IssuedSupply => Once,
             FieldType::ParentId => NoneOrOnce
         },
-        owned_rights: type_map! {
-            OwnedRightType::Assets => NoneOrMore,
-            OwnedRightType::Engraving => NoneOrMore
-        },
+        owned_rights,
         public_rights: none!(),
     }
 }
 
-fn issue() -> TransitionSchema {
+fn issue(version: SchemaVersion) -> TransitionSchema {
     use Occurrences::*;
 
+    let mut closes = type_map! {};
+    let mut owned_rights = type_map! {
+        OwnedRightType::Assets => NoneOrMore,
+        OwnedRightType::Engraving => NoneOrMore
+    };
+    // Secondary issue has to close inflation rights: embedded RGB validation
+    // script requires each of the issued allocations and of the new inflation
+    // rights to be backed by a closed inflation right
+    if version.has_inflation_rights() {
+        closes.insert(OwnedRightType::Inflation.into(), OnceOrMore);
+        owned_rights.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+    }
+
     TransitionSchema {
         metadata: type_map! {
             // We need this field in order to be able to verify pedersen
@@ -157,141 +192,163 @@ fn issue() -> TransitionSchema {
             FieldType::Data => NoneOrMore,
             FieldType::DataFormat => NoneOrOnce
         },
-        closes: none!(),
-        owned_rights: type_map! {
-            OwnedRightType::Assets => NoneOrMore,
-            OwnedRightType::Engraving => NoneOrMore
-        },
+        closes,
+        owned_rights,
         public_rights: none!(),
     }
 }
 
-/// Builds & returns complete RGB121 schema (root schema object)
-pub fn schema() -> Schema {
+fn transfer(version: SchemaVersion) -> TransitionSchema {
     use Occurrences::*;
 
+    let mut closes = type_map! {
+        OwnedRightType::Assets => OnceOrMore
+    };
+    let mut owned_rights = type_map! {
+        OwnedRightType::Assets => NoneOrMore
+    };
+    // Inflation rights are moved together with the assets allocated to the
+    // same outputs, otherwise spending the outputs would destroy them
+    if version.has_inflation_rights() {
+        closes.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+        owned_rights.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+    }
+
+    TransitionSchema {
+        metadata: none!(),
+        closes,
+        owned_rights,
+        public_rights: none!(),
+    }
+}
+
+fn engraving(version: SchemaVersion) -> TransitionSchema {
+    use Occurrences::*;
+
+    let mut closes = type_map! {
+        OwnedRightType::Assets => OnceOrMore,
+        OwnedRightType::Engraving => NoneOrMore
+    };
+    let mut owned_rights = type_map! {
+        OwnedRightType::Assets => NoneOrMore,
+        OwnedRightType::Engraving => NoneOrMore
+    };
+    if version.has_inflation_rights() {
+        closes.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+        owned_rights.insert(OwnedRightType::Inflation.into(), NoneOrMore);
+    }
+
+    TransitionSchema {
+        metadata: type_map! {
+            FieldType::Data => NoneOrMore,
+            FieldType::DataFormat => NoneOrOnce
+        },
+        closes,
+        owned_rights,
+        public_rights: none!(),
+    }
+}
+
+fn field_types() -> BTreeMap<rgb::schema::FieldType, TypeRef> {
+    type_map! {
+        // Rational: if we will use just 26 letters of English alphabet (and
+        // we are not limited by them), we will have 26^8 possible tickers,
+        // i.e. > 208 trillions, which is sufficient amount
+        FieldType::Name => TypeRef::ascii_string(),
+        FieldType::RicardianContract => TypeRef::ascii_string(),
+        FieldType::Description => TypeRef::ascii_string(),
+        FieldType::Data => TypeRef::bytes(),
+        FieldType::DataFormat => TypeRef::u16(),
+        // Contract text may contain URL, text or text representation of
+        // Ricardian contract, up to 64kb. If the contract doesn't fit, a
+        // double SHA256 hash and URL should be used instead, pointing to
+        // the full contract text, where hash must be represented by a
+        // hexadecimal string, optionally followed by `\n` and text URL;
+        // field values of any other form are the full contract text
+        FieldType::Precision => TypeRef::u8(),
+        // We need this b/c allocated amounts are hidden behind Pedersen
+        // commitments
+        FieldType::IssuedSupply => TypeRef::u64(),
+        // While UNIX timestamps allow negative numbers; in context of RGB
+        // Schema, assets can't be issued in the past before RGB or Bitcoin
+        // even existed; so we prohibit all the dates before RGB release
+        // This timestamp is equal to 10/10/2020 @ 2:37pm (UTC)
+        FieldType::Timestamp => TypeRef::i64(),
+        FieldType::ParentId => TypeRef::ascii_string()
+    }
+}
+
+fn owned_right_types(version: SchemaVersion) -> BTreeMap<rgb::schema::OwnedRightType, StateSchema> {
+    let mut types = type_map! {
+        OwnedRightType::Assets => StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit),
+        OwnedRightType::Engraving => StateSchema::DataContainer
+    };
+    // How much issuer can issue tokens on this path. If there is no limit,
+    // than `core::u64::MAX` / sum(inflation_assignments) must be used, as
+    // this will be a de-facto limit to the issuance
+    if version.has_inflation_rights() {
+        types.insert(
+            OwnedRightType::Inflation.into(),
+            StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit),
+        );
+    }
+    types
+}
+
+/// Builds & returns complete RGB121 schema (root schema object) of the latest
+/// version
+pub fn schema() -> Schema { root_schema(SchemaVersion::LATEST) }
+
+/// RGB121 subschema of the latest version, which allows simple asset
+/// transfers but no engraving
+pub fn subschema() -> Schema { sub_schema(SchemaVersion::LATEST) }
+
+/// Builds & returns complete RGB121 schema (root schema object) of the given
+/// version
+pub(crate) fn root_schema(version: SchemaVersion) -> Schema {
     Schema {
         rgb_features: none!(),
         root_id: none!(),
-        genesis: genesis(),
+        genesis: genesis(version),
         type_system: type_system(),
         extensions: none!(),
         transitions: type_map! {
-            TransitionType::Issue => issue(),
-            TransitionType::Transfer => TransitionSchema {
-                metadata: none!(),
-                closes: type_map! {
-                    OwnedRightType::Assets => OnceOrMore
-                },
-                owned_rights: type_map! {
-                    OwnedRightType::Assets => NoneOrMore
-                },
-                public_rights: none!()
-            },
-            TransitionType::Engraving => TransitionSchema {
-                metadata: type_map! {
-                    FieldType::Data => NoneOrMore,
-                    FieldType::DataFormat => NoneOrOnce
-                },
-                closes: type_map! {
-                    OwnedRightType::Assets => OnceOrMore,
-                    OwnedRightType::Engraving => NoneOrMore
-                },
-                owned_rights: type_map! {
-                    OwnedRightType::Assets => NoneOrMore,
-                    OwnedRightType::Engraving => NoneOrMore
-                },
-                public_rights: none!()
-            }
-        },
-        field_types: type_map! {
-            // Rational: if we will use just 26 letters of English alphabet (and
-            // we are not limited by them), we will have 26^8 possible tickers,
-            // i.e. > 208 trillions, which is sufficient amount
-            FieldType::Name => TypeRef::ascii_string(),
-            FieldType::RicardianContract => TypeRef::ascii_string(),
-            FieldType::Description => TypeRef::ascii_string(),
-            FieldType::Data => TypeRef::bytes(),
-            FieldType::DataFormat => TypeRef::u16(),
-            // Contract text may contain URL, text or text representation of
-            // Ricardian contract, up to 64kb. If the contract doesn't fit, a
-            // double SHA256 hash and URL should be used instead, pointing to
-            // the full contract text, where hash must be represented by a
-            // hexadecimal string, optionally followed by `\n` and text URL
-            FieldType::Precision => TypeRef::u8(),
-            // We need this b/c allocated amounts are hidden behind Pedersen
-            // commitments
-            FieldType::IssuedSupply => TypeRef::u64(),
-            // While UNIX timestamps allow negative numbers; in context of RGB
-            // Schema, assets can't be issued in the past before RGB or Bitcoin
-            // even existed; so we prohibit all the dates before RGB release
-            // This timestamp is equal to 10/10/2020 @ 2:37pm (UTC)
-            FieldType::Timestamp => TypeRef::i64(),
-            FieldType::ParentId => TypeRef::ascii_string()
-        },
-        owned_right_types: type_map! {
-            // How much issuer can issue tokens on this path. If there is no
-            // limit, than `core::u64::MAX` / sum(inflation_assignments)
-            // must be used, as this will be a de-facto limit to the
-            // issuance
-            OwnedRightType::Assets => StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit),
-            OwnedRightType::Engraving => StateSchema::DataContainer
+            TransitionType::Issue => issue(version),
+            TransitionType::Transfer => transfer(version),
+            TransitionType::Engraving => engraving(version)
         },
+        field_types: field_types(),
+        owned_right_types: owned_right_types(version),
         public_right_types: none!(),
+        // RGB121-specific rules can't be expressed with the validation script
+        // and are checked by wallets off-chain, see `rules` module
         script: ValidationScript::Embedded,
         override_rules: OverrideRules::AllowAnyVm,
     }
 }
 
-/// RGB121 subschema which allows simple asset transfers but no engraving
-pub fn subschema() -> Schema {
-    use Occurrences::*;
-
+/// RGB121 subschema of the given version, which allows simple asset transfers
+/// but no engraving
+pub(crate) fn sub_schema(version: SchemaVersion) -> Schema {
+    let root_id = match version {
+        SchemaVersion::V1 => SCHEMA_V1_ID_BECH32,
+        SchemaVersion::V2 => SCHEMA_ID_BECH32,
+    };
     Schema {
         rgb_features: none!(),
-        root_id: SchemaId::from_str(SCHEMA_ID_BECH32)
-            .expect("Broken root schema ID for RGB121 sub-schema"),
+        root_id: SchemaId::from_str(root_id).expect("Broken root schema ID for RGB121 sub-schema"),
         type_system: type_system(),
-        genesis: genesis(),
+        genesis: genesis(version),
         extensions: none!(),
         transitions: type_map! {
-            TransitionType::Issue => issue(),
-            TransitionType::Transfer => TransitionSchema {
-                metadata: none!(),
-                closes: type_map! {
-                    OwnedRightType::Assets => OnceOrMore
-                },
-                owned_rights: type_map! {
-                    OwnedRightType::Assets => NoneOrMore
-                },
-                public_rights: none!()
-            }
-        },
-        field_types: type_map! {
-            // Rational: if we will use just 26 letters of English alphabet (and
-            // we are not limited by them), we will have 26^8 possible tickers,
-            // i.e. > 208 trillions, which is sufficient amount
-            FieldType::Name => TypeRef::ascii_string(),
-            FieldType::RicardianContract => TypeRef::ascii_string(),
-            FieldType::Description => TypeRef::ascii_string(),
-            FieldType::Data => TypeRef::bytes(),
-            FieldType::DataFormat => TypeRef::u16(),
-            FieldType::Precision => TypeRef::u8(),
-            // We need this b/c allocated amounts are hidden behind Pedersen
-            // commitments
-            FieldType::IssuedSupply => TypeRef::u64(),
-            // While UNIX timestamps allow negative numbers; in context of RGB
-            // Schema, assets can't be issued in the past before RGB or Bitcoin
-            // even existed; so we prohibit all the dates before RGB release
-            // This timestamp is equal to 10/10/2020 @ 2:37pm (UTC)
-            FieldType::Timestamp => TypeRef::i64(),
-            FieldType::ParentId => TypeRef::ascii_string()
-        },
-        owned_right_types: type_map! {
-            OwnedRightType::Assets => StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit),
-            OwnedRightType::Engraving => StateSchema::DataContainer
+            TransitionType::Issue => issue(version),
+            TransitionType::Transfer => transfer(version)
         },
+        field_types: field_types(),
+        owned_right_types: owned_right_types(version),
         public_right_types: none!(),
+        // RGB121-specific rules can't be expressed with the validation script
+        // and are checked by wallets off-chain, see `rules` module
         script: ValidationScript::Embedded,
         override_rules: OverrideRules::AllowAnyVm,
     }
@@ -312,8 +369,10 @@ mod test {
         assert_eq!(id.to_string(), SCHEMA_ID_BECH32);
         assert_eq!(
             id.to_string(),
-            "rgbsh1ykclt9qxkskqt88dwgccsp4w624k7adjwj06sknjkh04ygtc7rqsnykld7"
+            "rgbsh17290yrmpcyu6amd5ecte6jfzcfdpz73deakzlrav5pa2udv9dq6s05yanc"
         );
+        let id = root_schema(SchemaVersion::V1).schema_id();
+        assert_eq!(id.to_string(), SCHEMA_V1_ID_BECH32);
     }
 
     #[test]
@@ -322,13 +381,16 @@ mod test {
         assert_eq!(id.to_string(), SUBSCHEMA_ID_BECH32);
         assert_eq!(
             id.to_string(),
-            "rgbsh1ep4k4qvghntwptcn0gqmfpdvr8vz3amslvy4pc7s32u7h500l5hqxlzjsk"
+            "rgbsh1pprvlwyvsnzw6vhg5utnp8776v3cvx08hu5ssf3l4gzpsws3tvrs4lm8a3"
         );
+        let id = sub_schema(SchemaVersion::V1).schema_id();
+        assert_eq!(id.to_string(), SUBSCHEMA_V1_ID_BECH32);
     }
 
     #[test]
     fn schema_strict_encode() {
-        let data = schema()
+        let schema = root_schema(SchemaVersion::V1);
+        let data = schema
             .strict_serialize()
             .expect("RGB-121 schema serialization failed");
 
@@ -338,8 +400,8 @@ mod test {
         let schema121 =
             Schema::strict_deserialize(data).expect("RGB-121 schema deserialization failed");
 
-        assert_eq!(schema(), schema121);
-        assert_eq!(format!("{:#?}", schema()), format!("{:#?}", schema121));
+        assert_eq!(schema, schema121);
+        assert_eq!(format!("{:#?}", schema), format!("{:#?}", schema121));
         assert_eq!(
             bech32data,
             "z1qxz56wgwcfqqe894xuf8fzlcqqlc9q386zjgzfjg8sn5fapuytlkyxtmjv3g0994wej8k0pnmp7gy8wweln0\
@@ -352,5 +414,7 @@ mod test {
     fn subschema_verify() {
         let status = subschema().schema_verify(&schema());
         assert_eq!(status.validity(), Validity::Valid);
+        let status = sub_schema(SchemaVersion::V1).schema_verify(&root_schema(SchemaVersion::V1));
+        assert_eq!(status.validity(), Validity::Valid);
     }
 }
@@ -0,0 +1,444 @@
+//! Automatic selection of the asset allocations spent by state transfers.
+//!
+//! Since state transition spends all allocations assigned to the transaction
+//! outputs it closes, the selection operates on the transaction outputs with
+//! the total amount allocated to each of them, rather than on the individual
+//! allocations.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use bitcoin::OutPoint;
+use rgb::{seal, AtomicValue, EndpointValueMap, Transition};
+
+use crate::transitions::checked_sum;
+use crate::{Asset, TransitionError};
+
+/// Maximal number of the tried input combinations during the search for the
+/// exact match
+const EXACT_MATCH_MAX_TRIES: usize = 100_000;
+
+/// Strategies for picking transaction outputs spent by a state transfer
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum SelectionStrategy {
+    /// Spends outputs with the largest allocations first, consolidating the
+    /// assets
+    #[display("largest-first")]
+    LargestFirst,
+
+    /// Spends a set of outputs which sum exactly to the requested amount,
+    /// avoiding change
+    #[display("exact-match")]
+    ExactMatch,
+
+    /// Spends the smallest possible number of outputs, selected as with
+    /// [`SelectionStrategy::LargestFirst`], and then replaces the last of them
+    /// with the smallest output still covering the remaining amount. This
+    /// heuristic reduces the change, but does not guarantee the smallest
+    /// change among all the sets of the same size
+    #[display("minimize-inputs")]
+    MinimizeInputs,
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> Self { SelectionStrategy::LargestFirst }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "largest-first" | "largest" => SelectionStrategy::LargestFirst,
+            "exact-match" | "exact" => SelectionStrategy::ExactMatch,
+            "minimize-inputs" | "min-inputs" => SelectionStrategy::MinimizeInputs,
+            _ => return Err(Error::UnknownStrategy(s.to_owned())),
+        })
+    }
+}
+
+/// Errors happening during selection of the spent allocations
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// unknown coin selection strategy '{0}'
+    UnknownStrategy(String),
+
+    /// requested amount must be non-zero
+    ZeroAmount,
+
+    /// insufficient funds: {available} are known, while {requested} is
+    /// requested
+    InsufficientFunds {
+        /// Total amount of known allocations
+        available: AtomicValue,
+        /// Requested amount
+        requested: AtomicValue,
+    },
+
+    /// there is no set of transaction outputs holding exactly {0}
+    NoExactMatch(AtomicValue),
+
+    /// total amount of the selected allocations exceeds the maximal asset
+    /// amount
+    Overflow,
+
+    /// unable to construct state transition: {0}
+    #[from]
+    Transition(TransitionError),
+}
+
+/// Transaction outputs selected for spending by a state transfer
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CoinSelection {
+    /// Selected transaction outputs
+    pub inputs: BTreeSet<OutPoint>,
+    /// Total amount allocated to the selected outputs
+    pub total: AtomicValue,
+    /// Amount which has to be returned to the change output
+    pub change: AtomicValue,
+}
+
+impl Asset {
+    /// Selects transaction outputs holding at least `amount` of the asset
+    /// using the provided `strategy`.
+    pub fn select_coins(
+        &self,
+        amount: AtomicValue,
+        strategy: SelectionStrategy,
+    ) -> Result<CoinSelection, Error> {
+        self.select_coins_from(None, amount, strategy)
+    }
+
+    /// Selects transaction outputs holding at least `amount` of the asset
+    /// using the provided `strategy`, considering only the outputs from the
+    /// `pool` (if provided).
+    pub(crate) fn select_coins_from(
+        &self,
+        pool: Option<&BTreeSet<OutPoint>>,
+        amount: AtomicValue,
+        strategy: SelectionStrategy,
+    ) -> Result<CoinSelection, Error> {
+        if amount == 0 {
+            return Err(Error::ZeroAmount);
+        }
+
+        // Closing an output with a confidential allocation would destroy it,
+        // so such outputs are never selected
+        let confidential = self.confidential_outpoints().collect::<BTreeSet<_>>();
+        let mut balances = BTreeMap::<OutPoint, AtomicValue>::new();
+        let coins = self.known_coins().filter(|coin| {
+            pool.map(|pool| pool.contains(&coin.seal)).unwrap_or(true)
+                && !confidential.contains(&coin.seal)
+        });
+        for coin in coins {
+            let balance = balances.entry(coin.seal).or_default();
+            *balance = balance
+                .checked_add(coin.state.value)
+                .ok_or(Error::Overflow)?;
+        }
+        // Largest outputs go first; ties are resolved by outpoint order, making
+        // the selection deterministic
+        let mut candidates = balances.into_iter().collect::<Vec<_>>();
+        candidates.sort_by(|(a, a_value), (b, b_value)| b_value.cmp(a_value).then(a.cmp(b)));
+
+        // The available amount is used only for the comparison with the
+        // requested one, so it saturates instead of overflowing
+        let available = candidates
+            .iter()
+            .fold(0, |sum: AtomicValue, (_, value)| sum.saturating_add(*value));
+        if available < amount {
+            return Err(Error::InsufficientFunds {
+                available,
+                requested: amount,
+            });
+        }
+
+        let selected = match strategy {
+            SelectionStrategy::LargestFirst => largest_first(&candidates, amount),
+            SelectionStrategy::ExactMatch => {
+                exact_match(&candidates, amount).ok_or(Error::NoExactMatch(amount))?
+            }
+            SelectionStrategy::MinimizeInputs => minimize_inputs(&candidates, amount),
+        };
+
+        let total = checked_sum(selected.iter().map(|(_, value)| *value)).ok_or(Error::Overflow)?;
+        Ok(CoinSelection {
+            inputs: selected.into_iter().map(|(outpoint, _)| outpoint).collect(),
+            total,
+            change: total - amount,
+        })
+    }
+
+    /// Constructs state transfer paying to the `payment` endpoints, which
+    /// spends transaction outputs picked with the `strategy` and returns the
+    /// change (if any) to `change_seal`.
+    pub fn transfer_selected(
+        &self,
+        payment: EndpointValueMap,
+        change_seal: seal::Revealed,
+        strategy: SelectionStrategy,
+    ) -> Result<(Transition, CoinSelection), Error> {
+        let amount =
+            checked_sum(payment.values().copied()).ok_or(TransitionError::OutputsOverflow)?;
+        let selection = self.select_coins(amount, strategy)?;
+        let change = if selection.change > 0 {
+            bmap! { change_seal => selection.change }
+        } else {
+            bmap! {}
+        };
+        let transition = self.transfer(selection.inputs.clone(), payment, change)?;
+        Ok((transition, selection))
+    }
+}
+
+fn largest_first(
+    candidates: &[(OutPoint, AtomicValue)],
+    amount: AtomicValue,
+) -> Vec<(OutPoint, AtomicValue)> {
+    let mut total = 0;
+    candidates
+        .iter()
+        .take_while(|(_, value)| {
+            let take = total < amount;
+            total = value.saturating_add(total);
+            take
+        })
+        .copied()
+        .collect()
+}
+
+fn minimize_inputs(
+    candidates: &[(OutPoint, AtomicValue)],
+    amount: AtomicValue,
+) -> Vec<(OutPoint, AtomicValue)> {
+    // Largest-first selection has the smallest number of inputs; the last of
+    // them is replaced with the smallest output still covering the remainder
+    let mut selected = largest_first(candidates, amount);
+    let (_, last) = selected.pop().expect("selection is never empty");
+    // The selected outputs except the last one sum to less than the amount
+    let remainder = amount - selected.iter().map(|(_, value)| value).sum::<AtomicValue>();
+    let replacement = candidates[selected.len()..]
+        .iter()
+        .rev()
+        .find(|(_, value)| *value >= remainder)
+        .copied()
+        .expect("the last selected output covers the remainder");
+    debug_assert!(replacement.1 <= last);
+    selected.push(replacement);
+    selected
+}
+
+fn exact_match(
+    candidates: &[(OutPoint, AtomicValue)],
+    amount: AtomicValue,
+) -> Option<Vec<(OutPoint, AtomicValue)>> {
+    // Depth-first search over the candidates sorted in descending order, which
+    // tends to find the solutions with fewer inputs first. The remaining sum of
+    // the candidates is used to prune the branches which can't reach the
+    // amount.
+    let mut remaining = vec![0; candidates.len() + 1];
+    for (index, (_, value)) in candidates.iter().enumerate().rev() {
+        remaining[index] = value.saturating_add(remaining[index + 1]);
+    }
+
+    fn search(
+        candidates: &[(OutPoint, AtomicValue)],
+        remaining: &[AtomicValue],
+        index: usize,
+        target: AtomicValue,
+        selected: &mut Vec<usize>,
+        tries: &mut usize,
+    ) -> bool {
+        if target == 0 {
+            return true;
+        }
+        if index >= candidates.len() || remaining[index] < target || *tries >= EXACT_MATCH_MAX_TRIES
+        {
+            return false;
+        }
+        *tries += 1;
+        let (_, value) = candidates[index];
+        if value <= target {
+            selected.push(index);
+            if search(
+                candidates,
+                remaining,
+                index + 1,
+                target - value,
+                selected,
+                tries,
+            ) {
+                return true;
+            }
+            selected.pop();
+        }
+        search(candidates, remaining, index + 1, target, selected, tries)
+    }
+
+    let mut selected = vec![];
+    let mut tries = 0;
+    search(candidates, &remaining, 0, amount, &mut selected, &mut tries).then(|| {
+        selected
+            .into_iter()
+            .map(|index| candidates[index])
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use commit_verify::CommitConceal;
+    use proptest::prelude::*;
+    use rgb::{Node, SealEndpoint};
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::test_helpers::{contract, mixed_transfer, outpoint};
+    use crate::OwnedRightType;
+
+    /// Asset with allocations of 8, 5, 4 and 2 to the outputs 0 to 3
+    fn asset() -> Asset { issue(&[8, 5, 4, 2]) }
+
+    fn issue(amounts: &[AtomicValue]) -> Asset {
+        let allocations = (0..).zip(amounts.iter().copied()).collect::<Vec<_>>();
+        Asset::try_from(&contract(&allocations)).unwrap()
+    }
+
+    #[test]
+    fn strategies() {
+        let asset = asset();
+
+        let selection = asset
+            .select_coins(10, SelectionStrategy::LargestFirst)
+            .unwrap();
+        assert_eq!(selection, CoinSelection {
+            inputs: bset![outpoint(0), outpoint(1)],
+            total: 13,
+            change: 3,
+        });
+
+        let selection = asset
+            .select_coins(10, SelectionStrategy::MinimizeInputs)
+            .unwrap();
+        assert_eq!(selection, CoinSelection {
+            inputs: bset![outpoint(0), outpoint(3)],
+            total: 10,
+            change: 0,
+        });
+
+        let selection = asset
+            .select_coins(11, SelectionStrategy::ExactMatch)
+            .unwrap();
+        assert_eq!(selection, CoinSelection {
+            inputs: bset![outpoint(1), outpoint(2), outpoint(3)],
+            total: 11,
+            change: 0,
+        });
+
+        let selection = asset
+            .select_coins(3, SelectionStrategy::MinimizeInputs)
+            .unwrap();
+        assert_eq!(selection.inputs, bset![outpoint(2)]);
+        assert_eq!(selection.change, 1);
+    }
+
+    #[test]
+    fn failures() {
+        let asset = asset();
+        assert_eq!(
+            asset.select_coins(20, SelectionStrategy::LargestFirst),
+            Err(Error::InsufficientFunds {
+                available: 19,
+                requested: 20
+            })
+        );
+        assert_eq!(
+            asset.select_coins(0, SelectionStrategy::LargestFirst),
+            Err(Error::ZeroAmount)
+        );
+        assert_eq!(
+            asset.select_coins(18, SelectionStrategy::ExactMatch),
+            Err(Error::NoExactMatch(18))
+        );
+        assert_eq!(
+            SelectionStrategy::from_str("random"),
+            Err(Error::UnknownStrategy(s!("random")))
+        );
+        for strategy in [
+            SelectionStrategy::LargestFirst,
+            SelectionStrategy::ExactMatch,
+            SelectionStrategy::MinimizeInputs,
+        ] {
+            assert_eq!(
+                SelectionStrategy::from_str(&strategy.to_string()),
+                Ok(strategy)
+            );
+        }
+    }
+
+    #[test]
+    fn confidential() {
+        // The output 2 holds both a known and a confidential allocation
+        let contract = contract(&[(0, 10), (1, 3)]);
+        let asset = Asset::try_from(&mixed_transfer(&contract, 4, 6)).unwrap();
+        assert_eq!(asset.outpoint_coins(outpoint(2)).len(), 1);
+
+        let selection = asset.select_coins(3, SelectionStrategy::default()).unwrap();
+        assert_eq!(selection.inputs, bset![outpoint(1)]);
+        assert_eq!(
+            asset.select_coins(4, SelectionStrategy::LargestFirst),
+            Err(Error::InsufficientFunds {
+                available: 3,
+                requested: 4
+            })
+        );
+    }
+
+    #[test]
+    fn transfer() {
+        let asset = asset();
+        let beneficiary = SealEndpoint::ConcealedUtxo(
+            seal::Revealed::new(CloseMethod::OpretFirst, outpoint(10)).commit_conceal(),
+        );
+        let change = seal::Revealed::new(CloseMethod::OpretFirst, outpoint(11));
+
+        let (transition, selection) = asset
+            .transfer_selected(
+                bmap! { beneficiary => 7 },
+                change,
+                SelectionStrategy::LargestFirst,
+            )
+            .unwrap();
+        assert_eq!(selection.inputs, bset![outpoint(0)]);
+        assert_eq!(selection.change, 1);
+        let assignments = transition
+            .owned_rights()
+            .assignments_by_type(OwnedRightType::Assets as u16);
+        assert_eq!(assignments.len(), 2);
+        assert_eq!(assignments.filter_revealed_seals(), vec![change]);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn amounts_near_max(
+            amount in u64::MAX - 20..=u64::MAX,
+            strategy in prop_oneof![
+                Just(SelectionStrategy::LargestFirst),
+                Just(SelectionStrategy::ExactMatch),
+                Just(SelectionStrategy::MinimizeInputs),
+            ],
+        ) {
+            let asset = issue(&[u64::MAX - 10, 6, 4]);
+            match asset.select_coins(amount, strategy) {
+                Ok(selection) => prop_assert_eq!(selection.total - selection.change, amount),
+                Err(err) => {
+                    prop_assert_eq!(strategy, SelectionStrategy::ExactMatch);
+                    prop_assert_eq!(err, Error::NoExactMatch(amount));
+                }
+            }
+        }
+    }
+}
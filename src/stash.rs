@@ -0,0 +1,616 @@
+//! Directory-backed storage of RGB121 contracts and their history.
+//!
+//! The stash keeps a directory per contract, named after the contract id:
+//!
+//! ```text
+//! <contract_id>/contract         schema & genesis, without history
+//! <contract_id>/anchors/<txid>   anchors of witness transactions
+//! <contract_id>/bundles/<id>     witness txid and ids of bundled transitions
+//! <contract_id>/transitions/<id> revealed state transitions
+//! <contract_id>/extensions/<id>  state extensions
+//! attachments/<id>               attachment (media file) data
+//! witnesses/<txid>               witness transactions of the anchors
+//! ```
+//!
+//! Nodes are stored once, independently of the number of consignments they
+//! were imported from; data revealed by different consignments are merged.
+//! Consignments are validated before import; witness transactions which were
+//! used for the validation are kept, such that the history already known to
+//! the stash does not require them again.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+
+use amplify::IoError;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Transaction, Txid};
+use commit_verify::{lnpbp4, CommitVerify};
+use rgb::contract::attachment::AttachmentId;
+use rgb::reveal::MergeReveal;
+use rgb::{
+    Anchor, BundleId, Consignment, ConsignmentType, Contract, ContractId, Extension,
+    InmemConsignment, Node, NodeId, SchemaId, SealEndpoint, Transition, TransitionBundle, Validity,
+};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::{asset, Asset, SchemaRegistry, TxResolver, TxResolverError, ValidationReport};
+
+/// Errors happening during stash operations
+#[derive(Clone, PartialEq, Eq, Debug, Display, From, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// I/O error: {0}
+    #[from(io::Error)]
+    Io(IoError),
+
+    /// invalid strict-encoded data in the stash: {0}
+    Encoding(strict_encoding::Error),
+
+    /// contract {0} is not known to the stash
+    UnknownContract(ContractId),
+
+    /// attachment {0} is not known to the stash
+    UnknownAttachment(AttachmentId),
+
+    /// node {0} conflicts with the version already present in the stash
+    Conflict(NodeId),
+
+    /// consignment uses schema {0}, which is not a known RGB121 schema
+    UnsupportedSchema(SchemaId),
+
+    /// consignment is not valid and can't be imported; {0}
+    InvalidConsignment(String),
+
+    /// transition {0} and the other transitions of its bundle have no seals,
+    /// so they can't be validated
+    UnsealedTransition(NodeId),
+
+    /// invalid RGB121 asset: {0}
+    #[from]
+    Asset(asset::Error),
+}
+
+impl From<strict_encoding::Error> for Error {
+    fn from(err: strict_encoding::Error) -> Self {
+        match err {
+            strict_encoding::Error::Io(err) => Error::Io(err),
+            err => Error::Encoding(err),
+        }
+    }
+}
+
+/// Local storage of RGB121 contracts, their history and attachments
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Stash {
+    dir: PathBuf,
+}
+
+impl Stash {
+    /// Opens stash in the given directory, creating the directory if it does
+    /// not exist
+    pub fn open(dir: impl AsRef<Path>) -> Result<Stash, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Stash { dir })
+    }
+
+    /// Directory containing the stash data
+    pub fn dir(&self) -> &Path { &self.dir }
+
+    /// Lists ids of all contracts known to the stash
+    pub fn contract_ids(&self) -> Result<BTreeSet<ContractId>, Error> {
+        Ok(entries(&self.dir)?
+            .into_iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?;
+                ContractId::from_str(name).ok()
+            })
+            .filter(|contract_id| self.contract_dir(*contract_id).join("contract").is_file())
+            .collect())
+    }
+
+    /// Imports genesis, anchored state transitions and extensions from the
+    /// consignment, merging them with the data already present in the stash.
+    /// Returns the number of the nodes which were not known before.
+    ///
+    /// Before anything is written, the consignment schema is checked against
+    /// the [`SchemaRegistry`] and the consignment is validated with
+    /// [`ValidationReport`]. Witness transactions are resolved with the
+    /// `resolver`, falling back to the ones already known to the stash. Only
+    /// valid consignments are imported; the witness transactions of the
+    /// endpoints may be yet unknown.
+    pub fn import<T, R>(
+        &self,
+        consignment: &InmemConsignment<T>,
+        resolver: &R,
+    ) -> Result<usize, Error>
+    where
+        T: ConsignmentType,
+        R: TxResolver + ?Sized,
+    {
+        let schema_id = consignment.schema_id();
+        if !SchemaRegistry::new().is_supported(schema_id) {
+            return Err(Error::UnsupportedSchema(schema_id));
+        }
+        let resolver = Witnesses {
+            resolver,
+            stash: self,
+        };
+        validate(consignment, &resolver)?;
+
+        let contract_id = consignment.contract_id();
+        let dir = self.contract_dir(contract_id);
+        for subdir in ["anchors", "bundles", "transitions", "extensions"] {
+            fs::create_dir_all(dir.join(subdir))?;
+        }
+
+        let mut added = 0;
+        let path = dir.join("contract");
+        let genesis = if path.is_file() {
+            let contract = Contract::strict_file_load(&path)?;
+            merge(contract.genesis().clone(), consignment.genesis().clone())?
+        } else {
+            added += 1;
+            consignment.genesis().clone()
+        };
+        Contract::with(
+            consignment.schema().clone(),
+            consignment.root_schema().cloned(),
+            genesis,
+            empty!(),
+            empty!(),
+            empty!(),
+        )
+        .strict_file_save(path)?;
+
+        for (anchor, bundle) in consignment.anchored_bundles() {
+            let path = dir.join("anchors").join(anchor.txid.to_string());
+            if !path.is_file() {
+                anchor.strict_file_save(path)?;
+            }
+            if let Ok(tx) = resolver.resolver.resolve_tx(anchor.txid) {
+                self.store_witness(&tx)?;
+            }
+            let path = dir.join("bundles").join(bundle.bundle_id().to_string());
+            if !path.is_file() {
+                let inputs = bundle
+                    .revealed_iter()
+                    .map(|(transition, inputs)| (transition.node_id(), inputs.clone()))
+                    .chain(
+                        bundle
+                            .concealed_iter()
+                            .map(|(id, inputs)| (*id, inputs.clone())),
+                    )
+                    .collect::<BTreeMap<_, _>>();
+                (anchor.txid, inputs).strict_file_save(path)?;
+            }
+            for (transition, _) in bundle.revealed_iter() {
+                added += store_node(&dir.join("transitions"), transition.clone())?;
+            }
+        }
+        for extension in consignment.state_extensions() {
+            added += store_node(&dir.join("extensions"), extension.clone())?;
+        }
+
+        Ok(added)
+    }
+
+    /// Rebuilds consignment containing all the data known about the contract.
+    /// State transitions are ordered such that each of them follows the
+    /// transitions it spends.
+    pub fn consignment<T>(&self, contract_id: ContractId) -> Result<InmemConsignment<T>, Error>
+    where T: ConsignmentType {
+        let dir = self.contract_dir(contract_id);
+        let path = dir.join("contract");
+        if !path.is_file() {
+            return Err(Error::UnknownContract(contract_id));
+        }
+        let contract = Contract::strict_file_load(path)?;
+
+        let mut bundles = vec![];
+        for path in entries(&dir.join("bundles"))? {
+            let (txid, inputs) = <(Txid, BTreeMap<NodeId, BTreeSet<u16>>)>::strict_file_load(path)?;
+            let anchor = Anchor::<lnpbp4::MerkleProof>::strict_file_load(
+                dir.join("anchors").join(txid.to_string()),
+            )?;
+            let mut bundle = TransitionBundle::try_from(inputs)
+                .map_err(|_| strict_encoding::Error::DataIntegrityError(s!("empty bundle")))?;
+            for node_id in bundle.node_ids() {
+                let path = dir.join("transitions").join(node_id.to_string());
+                if path.is_file() {
+                    bundle
+                        .reveal_transition(Transition::strict_file_load(path)?)
+                        .map_err(|_| Error::Conflict(node_id))?;
+                }
+            }
+            bundles.push((anchor, bundle));
+        }
+        let extensions = entries(&dir.join("extensions"))?
+            .into_iter()
+            .map(Extension::strict_file_load)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(InmemConsignment::with(
+            contract.schema().clone(),
+            contract.root_schema().cloned(),
+            contract.genesis().clone(),
+            empty!(),
+            topological_order(bundles).try_into()?,
+            extensions.try_into()?,
+        ))
+    }
+
+    /// Rebuilds asset state from all the data known about the contract
+    pub fn asset(&self, contract_id: ContractId) -> Result<Asset, Error> {
+        let contract: Contract = self.consignment(contract_id)?;
+        Ok(Asset::try_from(&contract)?)
+    }
+
+    /// Stores attachment (media file) data, returning attachment id
+    pub fn store_attachment(&self, data: &[u8]) -> Result<AttachmentId, Error> {
+        let id = AttachmentId::commit(&sha256::Hash::hash(data));
+        let dir = self.dir.join("attachments");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(id.to_string()), data)?;
+        Ok(id)
+    }
+
+    /// Retrieves attachment (media file) data
+    pub fn attachment(&self, id: AttachmentId) -> Result<Vec<u8>, Error> {
+        let path = self.dir.join("attachments").join(id.to_string());
+        if !path.is_file() {
+            return Err(Error::UnknownAttachment(id));
+        }
+        Ok(fs::read(path)?)
+    }
+
+    fn contract_dir(&self, contract_id: ContractId) -> PathBuf {
+        self.dir.join(contract_id.to_string())
+    }
+
+    fn witness_path(&self, txid: Txid) -> PathBuf {
+        self.dir.join("witnesses").join(txid.to_string())
+    }
+
+    fn store_witness(&self, tx: &Transaction) -> Result<(), Error> {
+        let path = self.witness_path(tx.txid());
+        if !path.is_file() {
+            fs::create_dir_all(self.dir.join("witnesses"))?;
+            fs::write(path, bitcoin::consensus::serialize(tx))?;
+        }
+        Ok(())
+    }
+}
+
+impl TxResolver for Stash {
+    /// Resolves witness transactions kept by the stash
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        fs::read(self.witness_path(txid))
+            .ok()
+            .and_then(|data| bitcoin::consensus::deserialize(&data).ok())
+            .ok_or_else(|| TxResolverError::with(txid))
+    }
+}
+
+/// Transaction resolver used during the import, looking up witness
+/// transactions known to the stash if the provided resolver fails
+struct Witnesses<'resolver, R>
+where R: TxResolver + ?Sized
+{
+    resolver: &'resolver R,
+    stash: &'resolver Stash,
+}
+
+impl<'resolver, R> TxResolver for Witnesses<'resolver, R>
+where R: TxResolver + ?Sized
+{
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.resolver
+            .resolve_tx(txid)
+            .or_else(|_| self.stash.resolve_tx(txid))
+    }
+}
+
+/// Validates all the state transitions of the consignment.
+///
+/// RGB core validates only the history of the consignment endpoints, while
+/// the stash imports all the transitions; so each of the transitions is
+/// validated as an endpoint, using one of its own seals. Transitions without
+/// seals are validated only as a part of the bundles containing other
+/// endpoints. Only witness transactions of the original endpoints may be
+/// unknown.
+fn validate<T, R>(consignment: &InmemConsignment<T>, resolver: &R) -> Result<(), Error>
+where
+    T: ConsignmentType,
+    R: TxResolver + ?Sized,
+{
+    let mut endseals = consignment
+        .endpoints()
+        .copied()
+        .collect::<Vec<(BundleId, SealEndpoint)>>();
+    for (_, bundle) in consignment.anchored_bundles() {
+        let bundle_id = bundle.bundle_id();
+        for (transition, _) in bundle.revealed_iter() {
+            if let Some(seal) = transition.to_confiential_seals().into_iter().next() {
+                endseals.push((bundle_id, SealEndpoint::ConcealedUtxo(seal)));
+            }
+        }
+        if !endseals.iter().any(|(id, _)| *id == bundle_id) {
+            let node_id = bundle
+                .revealed_iter()
+                .next()
+                .map(|(transition, _)| transition.node_id())
+                .unwrap_or_default();
+            return Err(Error::UnsealedTransition(node_id));
+        }
+    }
+    let endpoint_txids = consignment
+        .anchored_bundles()
+        .filter(|(_, bundle)| {
+            consignment
+                .endpoints()
+                .any(|(bundle_id, _)| *bundle_id == bundle.bundle_id())
+        })
+        .map(|(anchor, _)| anchor.txid)
+        .collect::<BTreeSet<_>>();
+
+    let full = InmemConsignment::<T>::with(
+        consignment.schema().clone(),
+        consignment.root_schema().cloned(),
+        consignment.genesis().clone(),
+        endseals,
+        consignment
+            .anchored_bundles()
+            .map(|(anchor, bundle)| (anchor.clone(), bundle.clone()))
+            .collect::<Vec<_>>()
+            .try_into()?,
+        consignment
+            .state_extensions()
+            .cloned()
+            .collect::<Vec<_>>()
+            .try_into()?,
+    );
+    let report = ValidationReport::with(&full, resolver);
+    let valid = match report.validity() {
+        Validity::Valid => true,
+        Validity::ValidExceptEndpoints => report
+            .status
+            .unmined_endpoint_txids
+            .iter()
+            .all(|txid| endpoint_txids.contains(txid)),
+        Validity::UnresolvedTransactions | Validity::Invalid => false,
+    };
+    if !valid {
+        return Err(Error::InvalidConsignment(report.to_string()));
+    }
+    Ok(())
+}
+
+/// Lists directory entries in a deterministic order; missing directory is
+/// treated as empty
+fn entries(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Saves node into the directory, merging it with the already stored version.
+/// Returns 1 if the node was not known before and 0 otherwise.
+fn store_node<N>(dir: &Path, node: N) -> Result<usize, Error>
+where N: Node + MergeReveal + StrictEncode + StrictDecode {
+    let path = dir.join(node.node_id().to_string());
+    let (node, added) =
+        if path.is_file() { (merge(N::strict_file_load(&path)?, node)?, 0) } else { (node, 1) };
+    node.strict_file_save(path)?;
+    Ok(added)
+}
+
+fn merge<N>(stored: N, imported: N) -> Result<N, Error>
+where N: Node + MergeReveal {
+    let node_id = stored.node_id();
+    stored
+        .merge_reveal(imported)
+        .map_err(|_| Error::Conflict(node_id))
+}
+
+/// Orders anchored bundles such that bundles spending outputs of other bundles
+/// go after them
+pub(crate) fn topological_order(
+    mut pending: Vec<(Anchor<lnpbp4::MerkleProof>, TransitionBundle)>,
+) -> Vec<(Anchor<lnpbp4::MerkleProof>, TransitionBundle)> {
+    let known = pending
+        .iter()
+        .flat_map(|(_, bundle)| bundle.node_ids())
+        .collect::<BTreeSet<_>>();
+    let mut placed = BTreeSet::new();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(_, bundle)| {
+            bundle
+                .known_transitions()
+                .flat_map(|transition| transition.parent_owned_rights().keys())
+                .all(|parent_id| !known.contains(parent_id) || placed.contains(parent_id))
+        });
+        // Dependency cycles are impossible in valid history; if the data are
+        // corrupted, the remaining bundles are kept in the original order
+        let (anchor, bundle) = pending.remove(ready.unwrap_or(0));
+        placed.extend(bundle.node_ids());
+        ordered.push((anchor, bundle));
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use bitcoin::{OutPoint, Transaction};
+    use rgb::{seal, ParentOwnedRights, RevealSeals, StateTransfer};
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::schema::TransitionType;
+    use crate::test_helpers::{self, outpoint, witness_tx};
+    use crate::{complete_commitment, embed_transition, finalize_transfer, MemTxResolver};
+
+    fn stash(name: &str) -> Stash {
+        let dir =
+            std::env::temp_dir().join(format!("rgb121-{}-stash-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        Stash::open(dir).unwrap()
+    }
+
+    /// Moves all the assets from `input` to the explicit seal on `output`,
+    /// returning the sender copy of the state transfer with the transition
+    /// and the witness transaction
+    fn transfer<T>(
+        source: &InmemConsignment<T>,
+        input: OutPoint,
+        output: OutPoint,
+    ) -> (StateTransfer, Transaction)
+    where
+        T: ConsignmentType,
+    {
+        let asset = Asset::try_from(source).unwrap();
+        let change = seal::Revealed::new(CloseMethod::OpretFirst, output);
+        let transition = asset
+            .transfer(bset![input], bmap! {}, bmap! { change => 5 })
+            .unwrap();
+        let tx = witness_tx(input);
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        embed_transition(&mut psbt, source, transition).unwrap();
+        complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0).unwrap();
+        let mut transfer = finalize_transfer(&psbt, source).unwrap();
+        transfer.reveal_seals(&[change]);
+        (transfer, psbt.unsigned_tx)
+    }
+
+    fn contract() -> Contract { crate::test_helpers::contract(&[(0, 5)]) }
+
+    #[test]
+    fn import() {
+        let stash = stash("import");
+        let contract = contract();
+        let contract_id = contract.contract_id();
+        let resolver = MemTxResolver::default();
+        assert_eq!(stash.import(&contract, &resolver), Ok(1));
+        assert_eq!(stash.contract_ids(), Ok(bset![contract_id]));
+
+        let (first, first_tx) = transfer(&contract, outpoint(0), outpoint(1));
+        let (second, second_tx) = transfer(&first, outpoint(1), outpoint(2));
+        // The first transition is imported only once, and the bundles are
+        // restored in the order of spending
+        let resolver = MemTxResolver::with([first_tx.clone(), second_tx]);
+        assert_eq!(stash.import(&second, &resolver), Ok(2));
+        // Witness transactions are remembered by the stash
+        assert_eq!(stash.resolve_tx(first_tx.txid()).unwrap(), first_tx);
+        assert_eq!(stash.import(&first, &MemTxResolver::default()), Ok(0));
+
+        let restored: StateTransfer = stash.consignment(contract_id).unwrap();
+        assert_eq!(
+            restored.anchored_bundles().collect::<Vec<_>>(),
+            second.anchored_bundles().collect::<Vec<_>>()
+        );
+        let asset = stash.asset(contract_id).unwrap();
+        assert!(asset.outpoint_coins(outpoint(1)).is_empty());
+        assert_eq!(asset.spent_outpoints().count(), 2);
+
+        let unknown = ContractId::from_inner(Hash::hash(b"unknown"));
+        assert_eq!(
+            stash.asset(unknown).map(|_| ()),
+            Err(Error::UnknownContract(unknown))
+        );
+        fs::remove_dir_all(stash.dir()).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let stash = stash("invalid");
+        let contract = contract();
+        let (first, _) = transfer(&contract, outpoint(0), outpoint(1));
+        // The witness transaction of the history can't be resolved
+        let (second, _) = transfer(&first, outpoint(1), outpoint(2));
+        assert!(matches!(
+            stash.import(&second, &MemTxResolver::default()),
+            Err(Error::InvalidConsignment(_))
+        ));
+
+        let mut schema = contract.schema().clone();
+        schema.root_id = SchemaId::from_inner(Hash::hash(b"foreign"));
+        let schema_id = schema.schema_id();
+        let foreign = Contract::with(
+            schema,
+            None,
+            contract.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        assert_eq!(
+            stash.import(&foreign, &MemTxResolver::default()),
+            Err(Error::UnsupportedSchema(schema_id))
+        );
+
+        // Transition closing the genesis allocation without assigning any
+        // state has no seal to be validated as an endpoint
+        let coin = Asset::try_from(&contract)
+            .unwrap()
+            .known_coins()
+            .next()
+            .unwrap()
+            .outpoint;
+        let mut parent = ParentOwnedRights::default();
+        parent
+            .entry(coin.node_id)
+            .or_insert_with(|| empty!())
+            .entry(coin.ty)
+            .or_insert_with(|| empty!())
+            .push(coin.no);
+        let transition = Transition::with(
+            TransitionType::Transfer,
+            empty!(),
+            empty!(),
+            empty!(),
+            empty!(),
+            parent,
+        );
+        let node_id = transition.node_id();
+        let (anchor, bundle, _) = test_helpers::anchor(&contract, transition, outpoint(0));
+        let mut unsealed = StateTransfer::with(
+            contract.schema().clone(),
+            None,
+            contract.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        unsealed.push_anchored_bundle(anchor, bundle).unwrap();
+        assert_eq!(
+            stash.import(&unsealed, &MemTxResolver::default()),
+            Err(Error::UnsealedTransition(node_id))
+        );
+        assert_eq!(stash.contract_ids(), Ok(bset![]));
+        fs::remove_dir_all(stash.dir()).unwrap();
+    }
+
+    #[test]
+    fn attachments() {
+        let stash = stash("attachments");
+        let id = stash.store_attachment(b"collectible").unwrap();
+        assert_eq!(stash.attachment(id), Ok(b"collectible".to_vec()));
+
+        let unknown = AttachmentId::commit(&sha256::Hash::hash(b"unknown"));
+        assert_eq!(
+            stash.attachment(unknown),
+            Err(Error::UnknownAttachment(unknown))
+        );
+        fs::remove_dir_all(stash.dir()).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! Fixtures shared by the unit tests of the crate modules.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fs, process};
+
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::{
+    OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Txid, WPubkeyHash,
+    Witness,
+};
+use commit_verify::{lnpbp4, CommitConceal};
+use lnpbp::chain::Chain;
+use rgb::fungible::allocation::OutpointValue;
+use rgb::psbt::{RgbExt, RgbInExt};
+use rgb::{
+    seal, Anchor, AtomicValue, ConcealState, Consignment, Contract, Node, SealEndpoint,
+    StateTransfer, Transition, TransitionBundle,
+};
+use seals::txout::CloseMethod;
+use stens::AsciiString;
+use wallet::psbt::{Psbt, PsbtVersion};
+
+use crate::{
+    complete_commitment, embed_transition, Asset, CreateError, FileAttachment, Rgb121,
+    RicardianContract,
+};
+
+/// Transaction, which outputs receive allocations of the issued test assets
+pub(crate) const TXID: &str = "5aa2d0a8098371ee12b4b59f43ffe6a2de637341258af65936a5baa01da49e9b";
+
+/// Output `vout` of the [`TXID`] transaction
+pub(crate) fn outpoint(vout: u32) -> OutPoint { OutPoint::new(Txid::from_str(TXID).unwrap(), vout) }
+
+/// Seal on the output `vout` of the [`TXID`] transaction
+pub(crate) fn seal(vout: u32) -> seal::Revealed {
+    seal::Revealed::new(CloseMethod::OpretFirst, outpoint(vout))
+}
+
+/// Concealed seal on the output `vout` of the [`TXID`] transaction
+pub(crate) fn beneficiary(vout: u32) -> SealEndpoint {
+    SealEndpoint::ConcealedUtxo(seal(vout).commit_conceal())
+}
+
+/// Parameters of a test asset issue; by default the asset is named
+/// "Collectible" and has no metadata, attachments, allocations and inflation
+/// rights
+pub(crate) struct Issuance {
+    pub name: &'static str,
+    pub description: Option<&'static str>,
+    pub attachments: Vec<FileAttachment>,
+    pub data: Vec<Vec<u8>>,
+    pub data_format: Option<u16>,
+    /// Amounts allocated to the outputs of the [`TXID`] transaction
+    pub allocations: Vec<(u32, AtomicValue)>,
+    /// Issue allowances of inflation rights assigned to the outputs of the
+    /// [`TXID`] transaction
+    pub inflation: Vec<(u32, AtomicValue)>,
+    pub method: CloseMethod,
+    pub ricardian_contract: Option<RicardianContract>,
+}
+
+impl Default for Issuance {
+    fn default() -> Self {
+        Issuance {
+            name: "Collectible",
+            description: None,
+            attachments: vec![],
+            data: vec![],
+            data_format: None,
+            allocations: vec![],
+            inflation: vec![],
+            method: CloseMethod::OpretFirst,
+            ricardian_contract: None,
+        }
+    }
+}
+
+impl Issuance {
+    /// Issue with the `allocations` of amounts to the outputs of the [`TXID`]
+    /// transaction
+    pub fn with(allocations: &[(u32, AtomicValue)]) -> Self {
+        Issuance {
+            allocations: allocations.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    pub fn create(self) -> Result<Contract, CreateError> {
+        Contract::create_rgb121(
+            Chain::Signet,
+            AsciiString::from_str(self.name).unwrap(),
+            self.description
+                .map(|description| AsciiString::from_str(description).unwrap()),
+            0,
+            None,
+            self.attachments,
+            self.data,
+            self.data_format,
+            outpoint_values(self.allocations),
+            outpoint_values(self.inflation),
+            self.method,
+            self.ricardian_contract,
+        )
+    }
+
+    pub fn contract(self) -> Contract { self.create().unwrap() }
+}
+
+fn outpoint_values(values: Vec<(u32, AtomicValue)>) -> Vec<OutpointValue> {
+    values
+        .into_iter()
+        .map(|(vout, value)| OutpointValue {
+            value,
+            outpoint: outpoint(vout),
+        })
+        .collect()
+}
+
+/// Issues "Collectible" asset with the `allocations` of amounts to the outputs
+/// of the [`TXID`] transaction
+pub(crate) fn contract(allocations: &[(u32, AtomicValue)]) -> Contract {
+    Issuance::with(allocations).contract()
+}
+
+/// Temporary file with a name unique across the test processes and threads,
+/// removed when dropped
+pub(crate) struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn with(contents: impl AsRef<[u8]>) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "rgb121-{}-{}.txt",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, contents).unwrap();
+        TempFile(path)
+    }
+
+    /// Plain text attachment of the file content
+    pub fn attachment(&self) -> FileAttachment {
+        FileAttachment {
+            file_path: self.0.clone(),
+            mime: AsciiString::from_str("text/plain").unwrap(),
+            salt: 1,
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) { let _ = fs::remove_file(&self.0); }
+}
+
+/// Transaction spending the `input` to a single P2WPKH output
+pub(crate) fn witness_tx(input: OutPoint) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: input,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+        }],
+    }
+}
+
+/// Commits the transition to a witness transaction spending the `input` with
+/// an OP_RETURN output, returning the anchor, the bundle with the transition
+/// and the witness transaction
+pub(crate) fn anchor(
+    contract: &Contract,
+    transition: Transition,
+    input: OutPoint,
+) -> (Anchor<lnpbp4::MerkleProof>, TransitionBundle, Transaction) {
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: input,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[0u8; 32]),
+        }],
+    };
+    let mut psbt = Psbt::with(tx, PsbtVersion::V0).unwrap();
+    psbt.outputs[0].set_opret_host().unwrap();
+    let contract_id = contract.contract_id();
+    psbt.set_rgb_contract(contract.clone()).unwrap();
+    psbt.inputs[0]
+        .set_rgb_consumer(contract_id, transition.node_id())
+        .unwrap();
+    psbt.push_rgb_transition(transition.clone()).unwrap();
+    psbt.rgb_bundle_to_lnpbp4().unwrap();
+    let anchor = Anchor::commit(&mut psbt)
+        .unwrap()
+        .into_merkle_proof(contract_id)
+        .unwrap();
+    let bundle = TransitionBundle::try_from(bmap! { transition => bset![0u16] }).unwrap();
+    (anchor, bundle, psbt.into_unsigned_tx())
+}
+
+/// State transfer moving the allocation on the output 0 of the [`TXID`]
+/// transaction to the output 2, which receives a `known` allocation together
+/// with an allocation of the `confidential` amount, concealed from the
+/// consignment. The amounts must sum to the spent allocation.
+pub(crate) fn mixed_transfer(
+    contract: &Contract,
+    known: AtomicValue,
+    confidential: AtomicValue,
+) -> StateTransfer {
+    let revealed = seal(2);
+    let concealed = seal(2);
+    let mut transition = Asset::try_from(contract)
+        .unwrap()
+        .transfer(bset![outpoint(0)], bmap! {}, bmap! {
+            revealed => known,
+            concealed => confidential
+        })
+        .unwrap();
+
+    let mut tx = witness_tx(outpoint(0));
+    tx.output.clear();
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    embed_transition(&mut psbt, contract, transition.clone()).unwrap();
+    let anchor = complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0)
+        .unwrap()
+        .into_merkle_proof(contract.contract_id())
+        .unwrap();
+
+    transition.conceal_state_except(&[revealed.commit_conceal()]);
+    let mut consignment = StateTransfer::with(
+        contract.schema().clone(),
+        None,
+        contract.genesis().clone(),
+        empty!(),
+        empty!(),
+        empty!(),
+    );
+    consignment
+        .push_anchored_bundle(
+            anchor,
+            TransitionBundle::try_from(bmap! { transition => bset![0u16] }).unwrap(),
+        )
+        .unwrap();
+    consignment
+}
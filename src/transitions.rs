@@ -1,12 +1,12 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use bitcoin::OutPoint;
-use rgb::fungible::allocation::{AllocationMap, AllocationValueMap, AllocationValueVec};
+use rgb::fungible::allocation::{AllocationValueMap, AllocationValueVec, IntoSealValueMap};
 use rgb::prelude::*;
 use seals::txout::ExplicitSeal;
 
-use super::schema::{OwnedRightType, TransitionType};
-use super::Asset;
+use super::schema::{FieldType, OwnedRightType, TransitionType};
+use super::{Asset, SchemaVersion};
 
 /// Errors happening during construction of RGB-121 asset state transitions
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error)]
@@ -15,8 +15,28 @@ pub enum Error {
     /// input {0} is not related to the contract
     UnrelatedInput(OutPoint),
 
-    /// sum of inputs and outputs is not equal
-    InputsNotEqualOutputs,
+    /// input {0} holds asset allocation of confidential amount, which can't be
+    /// spent
+    ConfidentialInput(OutPoint),
+
+    /// asset allocation on input {0} is already spent
+    SpentInput(OutPoint),
+
+    /// sum of the input allocations exceeds the maximal asset amount
+    InputsOverflow,
+
+    /// sum of the output amounts exceeds the maximal asset amount
+    OutputsOverflow,
+
+    /// sum of inputs {inputs} is not equal to the sum of outputs {outputs}
+    InputsNotEqualOutputs {
+        /// Total amount allocated to the inputs
+        inputs: AtomicValue,
+        /// Total amount assigned to the outputs
+        outputs: AtomicValue,
+        /// Amounts allocated to each of the inputs
+        per_input: BTreeMap<OutPoint, AtomicValue>,
+    },
 
     /// issue allowance {allowed} for the provided set of issue-controlling
     /// rights is insufficient to issue the requested amount {requested}
@@ -39,35 +59,160 @@ pub enum Error {
     /// method was provided with a set of seals for owned rights which are not
     /// a part of the asset data: {0:?}
     UnknownSeals(BTreeSet<OutPoint>),
+
+    /// RGB121 schemata do not support {0}
+    UnsupportedOperation(&'static str),
+
+    /// secondary issue must close at least one inflation right
+    NoInflationRights,
+
+    /// secondary issue closing {closed} inflation rights must assign the same
+    /// number of asset allocations and new inflation rights, while {assigned}
+    /// are requested
+    InflationRightsMismatch {
+        /// Number of the closed inflation rights
+        closed: usize,
+        /// Number of the assigned asset allocations and inflation rights
+        assigned: usize,
+    },
+
+    /// input {0} holds inflation rights, which would be destroyed by the
+    /// transition; they can be moved only by blank transitions or secondary
+    /// issue
+    InflationInput(OutPoint),
 }
 
 impl Asset {
-    /// Performs secondary issue closing an inflation-controlling seal over
-    /// inflation state transition, which is constructed and returned by this
-    /// function
+    /// Performs secondary issue, constructing and returning issue state
+    /// transition which closes inflation rights on the `closing` outputs,
+    /// allocates the newly issued assets and assigns the rest of the issue
+    /// allowance to the `next_inflation` rights.
+    ///
+    /// The issued amount together with the allowance of the new inflation
+    /// rights must be equal to the allowance of the closed rights. RGB
+    /// validation also requires each of the closed rights to back exactly one
+    /// of the asset allocations or new inflation rights, so their numbers must
+    /// match. Assets issued under [`SchemaVersion::V1`] schemata have no
+    /// inflation rights and can't be inflated.
     pub fn inflate(
         &self,
-        _closing: BTreeSet<OutPoint>,
-        _next_inflation: AllocationValueMap,
-        _allocations: AllocationValueVec,
+        closing: BTreeSet<OutPoint>,
+        next_inflation: AllocationValueMap,
+        allocations: AllocationValueVec,
     ) -> Result<Transition, Error> {
-        todo!()
+        if !self
+            .version()
+            .map_or(false, SchemaVersion::has_inflation_rights)
+        {
+            return Err(Error::UnsupportedOperation("inflation of v1 assets"));
+        }
+
+        let mut rights = Vec::<OwnedValue>::new();
+        let mut unknown = BTreeSet::<OutPoint>::new();
+        for outpoint in closing {
+            let outpoint_rights = self.outpoint_inflation(outpoint);
+            if outpoint_rights.is_empty() {
+                unknown.insert(outpoint);
+            }
+            rights.extend(outpoint_rights);
+        }
+        if !unknown.is_empty() {
+            return Err(Error::UnknownSeals(unknown));
+        }
+        if rights.is_empty() {
+            return Err(Error::NoInflationRights);
+        }
+
+        let allocations = allocations.into_seal_value_map();
+        let next_inflation = next_inflation.into_seal_value_map();
+        let issued = checked_sum(allocations.values().copied()).ok_or(Error::OutputsOverflow)?;
+        let next_allowed =
+            checked_sum(next_inflation.values().copied()).ok_or(Error::OutputsOverflow)?;
+        let requested = issued
+            .checked_add(next_allowed)
+            .ok_or(Error::OutputsOverflow)?;
+        let allowed = checked_sum(rights.iter().map(|right| right.state.value))
+            .ok_or(Error::InputsOverflow)?;
+        if requested > allowed {
+            return Err(Error::InsufficientIssueAllowance { allowed, requested });
+        }
+        if requested < allowed {
+            return Err(Error::SupplyMismatch {
+                assigned: allowed,
+                requested,
+            });
+        }
+        let assigned = allocations.len() + next_inflation.len();
+        if assigned != rights.len() {
+            return Err(Error::InflationRightsMismatch {
+                closed: rights.len(),
+                assigned,
+            });
+        }
+
+        // Embedded RGB validation script requires the issue to contain
+        // inflation rights even if none of them are assigned
+        let assignments = type_map! {
+            OwnedRightType::Assets =>
+            TypedAssignments::zero_balanced(
+                vec![value::Revealed {
+                    value: issued,
+                    blinding: secp256k1zkp::key::ONE_KEY.into(),
+                }],
+                allocations,
+                empty!(),
+            ),
+            OwnedRightType::Inflation =>
+            TypedAssignments::zero_balanced(
+                vec![value::Revealed {
+                    value: next_allowed,
+                    blinding: secp256k1zkp::key::ONE_KEY.into(),
+                }],
+                next_inflation,
+                empty!(),
+            )
+        };
+        let mut parent = ParentOwnedRights::default();
+        for right in rights {
+            parent
+                .entry(right.outpoint.node_id)
+                .or_insert_with(|| empty!())
+                .entry(OwnedRightType::Inflation.into())
+                .or_insert_with(|| empty!())
+                .push(right.outpoint.no);
+        }
+
+        let transition = Transition::with(
+            TransitionType::Issue,
+            type_map! { FieldType::IssuedSupply => field!(U64, issued) }.into(),
+            empty!(),
+            assignments.into(),
+            empty!(),
+            parent,
+        );
+
+        Ok(transition)
     }
 
     /// Opens a new epoch by closing epoch-controlling seal over epoch opening
-    /// state transition, which is constructed and returned by this function
+    /// state transition.
+    ///
+    /// RGB121 schemata do not define epochs, thus the method always fails with
+    /// [`Error::UnsupportedOperation`].
     pub fn epoch(
         &self,
         _closing: OutPoint,
         _next_epoch: Option<ExplicitSeal>,
         _burning_seal: Option<ExplicitSeal>,
     ) -> Result<Transition, Error> {
-        todo!()
+        Err(Error::UnsupportedOperation("epoch"))
     }
 
     /// Burns certain amount of the asset by closing burn-controlling seal over
-    /// proof-of-burn state transition, which is constructed and returned by
-    /// this function
+    /// proof-of-burn state transition.
+    ///
+    /// RGB121 schemata do not define burn operation, thus the method always
+    /// fails with [`Error::UnsupportedOperation`].
     pub fn burn(
         &self,
         _closing: OutPoint,
@@ -75,12 +220,14 @@ impl Asset {
         _burned_utxos: BTreeSet<OutPoint>,
         _next_burn: Option<ExplicitSeal>,
     ) -> Result<Transition, Error> {
-        todo!()
+        Err(Error::UnsupportedOperation("burn"))
     }
 
     /// Burns and re-allocates certain amount of the asset by closing
-    /// burn-controlling seal over proof-of-burn state transition, which is
-    /// constructed and returned by this function
+    /// burn-controlling seal over proof-of-burn state transition.
+    ///
+    /// RGB121 schemata do not define burn-and-replace operation, thus the
+    /// method always fails with [`Error::UnsupportedOperation`].
     pub fn burn_replace(
         &self,
         _closing: OutPoint,
@@ -89,7 +236,44 @@ impl Asset {
         _next_burn: Option<ExplicitSeal>,
         _allocations: AllocationValueVec,
     ) -> Result<Transition, Error> {
-        todo!()
+        Err(Error::UnsupportedOperation("burn-and-replace"))
+    }
+
+    /// Engraves data into the asset by constructing engraving state
+    /// transition, which spends assets from `inputs` and re-allocates them to
+    /// the payment and change outputs without changing the total amount.
+    pub fn engrave(
+        &self,
+        inputs: BTreeSet<OutPoint>,
+        data: Vec<Vec<u8>>,
+        data_format: Option<u16>,
+        payment: EndpointValueMap,
+        change: SealValueMap,
+    ) -> Result<Transition, Error> {
+        self.check_inflation(&inputs)?;
+        let (assignments, parent) = self.reallocate(inputs, payment, change)?;
+
+        let mut metadata = type_map! {};
+        if !data.is_empty() {
+            metadata.insert(
+                FieldType::Data.into(),
+                data.into_iter().map(data::Revealed::Bytes).collect(),
+            );
+        }
+        if let Some(format) = data_format {
+            metadata.insert(FieldType::DataFormat.into(), field!(U16, format));
+        }
+
+        let transition = Transition::with(
+            TransitionType::Engraving,
+            metadata.into(),
+            empty!(),
+            assignments,
+            empty!(),
+            parent,
+        );
+
+        Ok(transition)
     }
 
     /// Creates a fungible asset-specific state transition (i.e. RGB-121
@@ -100,24 +284,213 @@ impl Asset {
         payment: EndpointValueMap,
         change: SealValueMap,
     ) -> Result<Transition, Error> {
+        self.check_inflation(&inputs)?;
+        let (assignments, parent) = self.reallocate(inputs, payment, change)?;
+
+        let transition = Transition::with(
+            TransitionType::Transfer,
+            empty!(),
+            empty!(),
+            assignments,
+            empty!(),
+            parent,
+        );
+
+        Ok(transition)
+    }
+
+    /// Creates blank state transition moving all asset allocations, engraving
+    /// and inflation rights from the `spent_outpoints` to the `new_seal`. Blank
+    /// transitions must be committed by transactions spending outputs with
+    /// asset allocations for a purpose not related to the asset, otherwise the
+    /// allocations are lost.
+    ///
+    /// Returns `None` if there is nothing allocated to the `spent_outpoints`.
+    /// Inflation rights without assets are moved by secondary issue of zero
+    /// amount.
+    /// Since RGB121 schemata allow moving engraving rights only together with
+    /// the asset, fails with [`Error::UnsupportedOperation`] if the outpoints
+    /// hold engraving rights but no assets. Allocations of confidential amount
+    /// can't be moved, so if any of the `spent_outpoints` holds them the
+    /// method fails with [`Error::ConfidentialInput`] instead of producing a
+    /// transition which would lose them.
+    pub fn blank_transition(
+        &self,
+        spent_outpoints: BTreeSet<OutPoint>,
+        new_seal: seal::Revealed,
+    ) -> Result<Option<Transition>, Error> {
+        self.spend_outpoints(&spent_outpoints, bmap! {}, new_seal)
+    }
+
+    /// Spends all asset allocations, engraving and inflation rights on the
+    /// `spent_outpoints`, distributing the `payment` to its beneficiaries and
+    /// moving the rest of the asset together with all the rights to the
+    /// `change_seal`.
+    ///
+    /// Returns `None` if there is nothing allocated to the `spent_outpoints`
+    /// and nothing to pay. Fails with [`Error::ConfidentialInput`] if any of
+    /// the `spent_outpoints` holds allocations of confidential amount, which
+    /// would be lost otherwise.
+    pub(crate) fn spend_outpoints(
+        &self,
+        spent_outpoints: &BTreeSet<OutPoint>,
+        payment: EndpointValueMap,
+        change_seal: seal::Revealed,
+    ) -> Result<Option<Transition>, Error> {
+        if let Some(outpoint) = self
+            .confidential_outpoints()
+            .find(|outpoint| spent_outpoints.contains(outpoint))
+        {
+            return Err(Error::ConfidentialInput(*outpoint));
+        }
+        let inputs = spent_outpoints
+            .iter()
+            .copied()
+            .filter(|outpoint| !self.outpoint_coins(*outpoint).is_empty())
+            .collect::<BTreeSet<_>>();
+        let attachments = self
+            .known_attachments()
+            .filter(|attachment| spent_outpoints.contains(&attachment.seal))
+            .collect::<Vec<_>>();
+        let inflation = self
+            .known_inflation()
+            .filter(|right| spent_outpoints.contains(&right.seal))
+            .cloned()
+            .collect::<Vec<_>>();
+        if inputs.is_empty() {
+            if !attachments.is_empty() {
+                return Err(Error::UnsupportedOperation(
+                    "moving engraving rights without assets",
+                ));
+            }
+            if payment.is_empty() {
+                if inflation.is_empty() {
+                    return Ok(None);
+                }
+                let mut assignments = OwnedRights::default();
+                let mut parent = ParentOwnedRights::default();
+                move_inflation(inflation, change_seal, &mut assignments, &mut parent);
+                return Ok(Some(Transition::with(
+                    TransitionType::Issue,
+                    type_map! { FieldType::IssuedSupply => field!(U64, 0) }.into(),
+                    empty!(),
+                    assignments,
+                    empty!(),
+                    parent,
+                )));
+            }
+        }
+
+        let total = checked_sum(
+            inputs
+                .iter()
+                .flat_map(|outpoint| self.outpoint_coins(*outpoint))
+                .map(|coin| coin.state.value),
+        )
+        .ok_or(Error::InputsOverflow)?;
+        let amount = checked_sum(payment.values().copied()).ok_or(Error::OutputsOverflow)?;
+        // Insufficient inputs are reported by `reallocate`
+        let change = match total.checked_sub(amount) {
+            Some(change) if change > 0 || payment.is_empty() => bmap! { change_seal => change },
+            _ => bmap! {},
+        };
+        let (mut assignments, mut parent) = self.reallocate(inputs, payment, change)?;
+        if !inflation.is_empty() {
+            move_inflation(inflation, change_seal, &mut assignments, &mut parent);
+        }
+        if attachments.is_empty() {
+            return Ok(Some(Transition::with(
+                TransitionType::Transfer,
+                empty!(),
+                empty!(),
+                assignments,
+                empty!(),
+                parent,
+            )));
+        }
+
+        let engravings = attachments
+            .iter()
+            .map(|attachment| Assignment::Revealed {
+                seal: change_seal,
+                state: attachment.state.clone(),
+            })
+            .collect();
+        assignments.insert(
+            OwnedRightType::Engraving.into(),
+            TypedAssignments::Attachment(engravings),
+        );
+        for attachment in attachments {
+            parent
+                .entry(attachment.outpoint.node_id)
+                .or_insert_with(|| empty!())
+                .entry(OwnedRightType::Engraving.into())
+                .or_insert_with(|| empty!())
+                .push(attachment.outpoint.no);
+        }
+        Ok(Some(Transition::with(
+            TransitionType::Engraving,
+            empty!(),
+            empty!(),
+            assignments,
+            empty!(),
+            parent,
+        )))
+    }
+
+    /// Fails with [`Error::InflationInput`] if any of the `inputs` holds
+    /// inflation rights, which would be lost by the transition
+    fn check_inflation(&self, inputs: &BTreeSet<OutPoint>) -> Result<(), Error> {
+        match self
+            .known_inflation()
+            .find(|right| inputs.contains(&right.seal))
+        {
+            Some(right) => Err(Error::InflationInput(right.seal)),
+            None => Ok(()),
+        }
+    }
+
+    /// Spends all assets allocated to the `inputs`, distributing them between
+    /// payment and change outputs
+    fn reallocate(
+        &self,
+        inputs: BTreeSet<OutPoint>,
+        payment: EndpointValueMap,
+        change: SealValueMap,
+    ) -> Result<(OwnedRights, ParentOwnedRights), Error> {
         // Collecting all input allocations
         let mut input_usto = Vec::<OwnedValue>::new();
+        let mut per_input = BTreeMap::<OutPoint, AtomicValue>::new();
         for outpoint in inputs {
+            // Closing the seal would destroy the confidential allocation even
+            // when the output also holds known ones
+            if self.confidential_outpoints().any(|o| *o == outpoint) {
+                Err(Error::ConfidentialInput(outpoint))?
+            }
             let coins = self.outpoint_coins(outpoint);
             if coins.is_empty() {
+                if self.spent_outpoints().any(|o| *o == outpoint) {
+                    Err(Error::SpentInput(outpoint))?
+                }
                 Err(Error::UnrelatedInput(outpoint))?
             }
+            let amount = checked_sum(coins.iter().map(|coin| coin.state.value))
+                .ok_or(Error::InputsOverflow)?;
+            per_input.insert(outpoint, amount);
             input_usto.extend(coins);
         }
         // Computing sum of inputs
         let input_amounts: Vec<_> = input_usto.iter().map(|coin| coin.state).collect();
-        let total_inputs = input_amounts
-            .iter()
-            .fold(0u64, |acc, coin| acc + coin.value);
-        let total_outputs = change.sum() + payment.sum();
+        let total_inputs = checked_sum(per_input.values().copied()).ok_or(Error::InputsOverflow)?;
+        let total_outputs = checked_sum(change.values().chain(payment.values()).copied())
+            .ok_or(Error::OutputsOverflow)?;
 
         if total_inputs != total_outputs {
-            Err(Error::InputsNotEqualOutputs)?
+            Err(Error::InputsNotEqualOutputs {
+                inputs: total_inputs,
+                outputs: total_outputs,
+                per_input,
+            })?
         }
 
         let assignments = type_map! {
@@ -135,15 +508,426 @@ impl Asset {
                 .push(coin.outpoint.no);
         }
 
-        let transition = Transition::with(
-            TransitionType::Transfer,
+        Ok((assignments.into(), parent))
+    }
+}
+
+/// Assigns all the inflation `rights` to the `seal`, keeping their state, and
+/// adds them to the closed rights
+fn move_inflation(
+    rights: Vec<OwnedValue>,
+    seal: seal::Revealed,
+    assignments: &mut OwnedRights,
+    parent: &mut ParentOwnedRights,
+) {
+    let mut moved = vec![];
+    for right in rights {
+        parent
+            .entry(right.outpoint.node_id)
+            .or_insert_with(|| empty!())
+            .entry(OwnedRightType::Inflation.into())
+            .or_insert_with(|| empty!())
+            .push(right.outpoint.no);
+        moved.push(Assignment::Revealed {
+            seal,
+            state: right.state,
+        });
+    }
+    assignments.insert(
+        OwnedRightType::Inflation.into(),
+        TypedAssignments::Value(moved),
+    );
+}
+
+/// Sums asset amounts, returning `None` on overflow
+pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = AtomicValue>) -> Option<AtomicValue> {
+    amounts
+        .into_iter()
+        .try_fold(0u64, |sum, amount| sum.checked_add(amount))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::Wrapper;
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use bitcoin::{PackedLockTime, Script, Sequence, Transaction, TxIn, Witness};
+    use proptest::prelude::*;
+    use rgb::fungible::allocation::AllocatedValue;
+    use rgb::{Contract, Genesis, SchemaId, StateTransfer, TransitionBundle};
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::schema::root_schema;
+    use crate::test_helpers::{mixed_transfer, outpoint, seal, Issuance, TXID};
+    use crate::{complete_commitment, embed_transition, SCHEMA_V1_ID_BECH32};
+
+    fn asset() -> Asset { Asset::try_from(&contract()).unwrap() }
+
+    fn contract() -> Contract { crate::test_helpers::contract(&[(0, 10)]) }
+
+    #[test]
+    fn engrave() {
+        let asset = asset();
+        let inputs = bset![outpoint(0)];
+        let transition = asset
+            .engrave(
+                inputs.clone(),
+                vec![vec![0xca, 0xfe]],
+                Some(1),
+                bmap! {},
+                bmap! { seal(0) => 10 },
+            )
+            .unwrap();
+        assert_eq!(
+            transition.transition_type(),
+            TransitionType::Engraving as u16
+        );
+        assert_eq!(transition.metadata().u16(FieldType::DataFormat), vec![1]);
+        assert_eq!(
+            asset.engrave(inputs, vec![], None, bmap! {}, bmap! { seal(0) => 9 }),
+            Err(Error::InputsNotEqualOutputs {
+                inputs: 10,
+                outputs: 9,
+                per_input: bmap! { outpoint(0) => 10 }
+            })
+        );
+    }
+
+    #[test]
+    fn blank() {
+        let asset = asset();
+        let seal = seal(0);
+        let genesis_utxo = outpoint(0);
+        let unrelated = OutPoint {
+            vout: 1,
+            ..genesis_utxo
+        };
+        assert_eq!(asset.blank_transition(bset![unrelated], seal), Ok(None));
+
+        let transition = asset
+            .blank_transition(bset![genesis_utxo, unrelated], seal)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            transition.transition_type(),
+            TransitionType::Transfer as u16
+        );
+        assert_eq!(
+            transition,
+            asset
+                .transfer(bset![genesis_utxo], bmap! {}, bmap! { seal => 10 })
+                .unwrap()
+        );
+    }
+
+    /// Asset with 10 units on the output 0 and inflation rights allowing to
+    /// issue 10 and 5 units on the outputs 1 and 2
+    fn inflatable() -> Contract {
+        Issuance {
+            inflation: vec![(1, 10), (2, 5)],
+            ..Issuance::with(&[(0, 10)])
+        }
+        .contract()
+    }
+
+    fn allocations(amounts: &[(u32, AtomicValue)]) -> AllocationValueVec {
+        amounts
+            .iter()
+            .map(|(vout, value)| {
+                AllocatedValue::from_str(&format!("{}@opret1st:{}:{}", value, TXID, vout)).unwrap()
+            })
+            .collect()
+    }
+
+    fn explicit_seal(vout: u32) -> ExplicitSeal {
+        ExplicitSeal::from_str(&format!("opret1st:{}:{}", TXID, vout)).unwrap()
+    }
+
+    #[test]
+    fn inflate() {
+        let asset = Asset::try_from(&inflatable()).unwrap();
+        let closing = bset![outpoint(1), outpoint(2)];
+        let transition = asset
+            .inflate(
+                closing.clone(),
+                bmap! { explicit_seal(4) => 7 },
+                allocations(&[(3, 8)]),
+            )
+            .unwrap();
+        assert_eq!(transition.transition_type(), TransitionType::Issue as u16);
+        assert_eq!(transition.metadata().u64(FieldType::IssuedSupply), vec![8]);
+        assert_eq!(
+            transition
+                .parent_outputs()
+                .into_iter()
+                .map(|output| output.ty)
+                .collect::<Vec<_>>(),
+            vec![OwnedRightType::Inflation as u16; 2]
+        );
+        let next = transition
+            .owned_rights_by_type(OwnedRightType::Inflation.into())
+            .unwrap()
+            .as_revealed_state_values()
+            .unwrap();
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].value, 7);
+
+        assert_eq!(
+            asset.inflate(
+                closing.clone(),
+                bmap! { explicit_seal(4) => 8 },
+                allocations(&[(3, 8)])
+            ),
+            Err(Error::InsufficientIssueAllowance {
+                allowed: 15,
+                requested: 16
+            })
+        );
+        assert_eq!(
+            asset.inflate(closing.clone(), bmap! {}, allocations(&[(3, 8)])),
+            Err(Error::SupplyMismatch {
+                assigned: 15,
+                requested: 8
+            })
+        );
+        assert_eq!(
+            asset.inflate(closing, bmap! {}, allocations(&[(3, 8), (4, 3), (5, 4)])),
+            Err(Error::InflationRightsMismatch {
+                closed: 2,
+                assigned: 3
+            })
+        );
+        assert_eq!(
+            asset.inflate(bset![outpoint(0)], bmap! {}, allocations(&[(3, 10)])),
+            Err(Error::UnknownSeals(bset![outpoint(0)]))
+        );
+        assert_eq!(
+            asset.inflate(bset![], bmap! {}, allocations(&[(3, 10)])),
+            Err(Error::NoInflationRights)
+        );
+    }
+
+    #[test]
+    fn inflate_v1() {
+        let contract = inflatable();
+        let genesis = contract.genesis();
+        let genesis = Genesis::with(
+            SchemaId::from_str(SCHEMA_V1_ID_BECH32).unwrap(),
+            genesis.chain().clone(),
+            genesis.metadata().clone(),
+            genesis.owned_rights().clone().into_inner(),
+            bset![],
+        );
+        let contract = Contract::with(
+            root_schema(SchemaVersion::V1),
+            None,
+            genesis,
             empty!(),
             empty!(),
-            assignments.into(),
             empty!(),
-            parent,
         );
+        let asset = Asset::try_from(&contract).unwrap();
+        assert_eq!(
+            asset.inflate(bset![outpoint(1)], bmap! {}, allocations(&[(3, 10)])),
+            Err(Error::UnsupportedOperation("inflation of v1 assets"))
+        );
+    }
 
-        Ok(transition)
+    #[test]
+    fn inflation_inputs() {
+        let contract = Issuance {
+            inflation: vec![(0, 5), (1, 10)],
+            ..Issuance::with(&[(0, 10)])
+        }
+        .contract();
+        let asset = Asset::try_from(&contract).unwrap();
+        assert_eq!(
+            asset.transfer(bset![outpoint(0)], bmap! {}, bmap! { seal(2) => 10 }),
+            Err(Error::InflationInput(outpoint(0)))
+        );
+
+        // Rights are moved together with the assets
+        let transition = asset
+            .blank_transition(bset![outpoint(0)], seal(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            transition.transition_type(),
+            TransitionType::Transfer as u16
+        );
+        let moved = transition
+            .owned_rights_by_type(OwnedRightType::Inflation.into())
+            .unwrap()
+            .as_revealed_state_values()
+            .unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].value, 5);
+
+        // Rights without assets are moved by secondary issue of zero amount
+        let transition = asset
+            .blank_transition(bset![outpoint(1)], seal(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(transition.transition_type(), TransitionType::Issue as u16);
+        assert_eq!(transition.metadata().u64(FieldType::IssuedSupply), vec![0]);
+        assert_eq!(transition.parent_outputs().len(), 1);
+    }
+
+    #[test]
+    fn unsupported() {
+        let asset = asset();
+        let outpoint = outpoint(0);
+        assert_eq!(
+            asset.burn(outpoint, 1, bset![], None),
+            Err(Error::UnsupportedOperation("burn"))
+        );
+        assert_eq!(
+            asset.epoch(outpoint, None, None),
+            Err(Error::UnsupportedOperation("epoch"))
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        let contract = contract();
+        let genesis_utxo = outpoint(0);
+        let change_utxo = OutPoint::new(genesis_utxo.txid, 2);
+        let transition = Asset::try_from(&contract)
+            .unwrap()
+            .transfer(bset![genesis_utxo], bmap! {}, bmap! {
+                seal::Revealed::new(CloseMethod::OpretFirst, change_utxo) => 10
+            })
+            .unwrap();
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: genesis_utxo,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        })
+        .unwrap();
+        embed_transition(&mut psbt, &contract, transition.clone()).unwrap();
+        let anchor = complete_commitment(&mut psbt, CloseMethod::OpretFirst, 0)
+            .unwrap()
+            .into_merkle_proof(contract.contract_id())
+            .unwrap();
+
+        // The recipient of the consignment does not know the change amount
+        let mut transition = transition;
+        transition.conceal_state_except(&[]);
+        let mut consignment = StateTransfer::with(
+            contract.schema().clone(),
+            None,
+            contract.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        consignment
+            .push_anchored_bundle(
+                anchor,
+                TransitionBundle::try_from(bmap! { transition => bset![0u16] }).unwrap(),
+            )
+            .unwrap();
+
+        let asset = Asset::try_from(&consignment).unwrap();
+        assert_eq!(asset.known_coins().count(), 0);
+        assert_eq!(
+            asset.transfer(bset![genesis_utxo], bmap! {}, bmap! {}),
+            Err(Error::SpentInput(genesis_utxo))
+        );
+        assert_eq!(
+            asset.transfer(bset![change_utxo], bmap! {}, bmap! {}),
+            Err(Error::ConfidentialInput(change_utxo))
+        );
+        let unrelated = OutPoint::new(genesis_utxo.txid, 3);
+        assert_eq!(
+            asset.transfer(bset![unrelated], bmap! {}, bmap! {}),
+            Err(Error::UnrelatedInput(unrelated))
+        );
+    }
+
+    #[test]
+    fn mixed_input() {
+        let mixed_utxo = outpoint(2);
+        let consignment = mixed_transfer(&contract(), 4, 6);
+        let asset = Asset::try_from(&consignment).unwrap();
+        assert_eq!(asset.outpoint_coins(mixed_utxo).len(), 1);
+        assert_eq!(
+            asset.blank_transition(bset![mixed_utxo], seal(0)),
+            Err(Error::ConfidentialInput(mixed_utxo))
+        );
+        assert_eq!(
+            asset.transfer(bset![mixed_utxo], bmap! {}, bmap! { seal(3) => 4 }),
+            Err(Error::ConfidentialInput(mixed_utxo))
+        );
+        assert_eq!(
+            asset.engrave(
+                bset![mixed_utxo],
+                vec![],
+                None,
+                bmap! {},
+                bmap! { seal(3) => 4 }
+            ),
+            Err(Error::ConfidentialInput(mixed_utxo))
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let asset = asset();
+        let inputs = bset![outpoint(0)];
+        let beneficiary = SealEndpoint::from(seal(0));
+        assert_eq!(
+            asset.transfer(
+                inputs,
+                bmap! { beneficiary => 1 },
+                bmap! { seal(0) => u64::MAX }
+            ),
+            Err(Error::OutputsOverflow)
+        );
+        let asset = Asset::try_from(&inflatable()).unwrap();
+        assert_eq!(
+            asset.inflate(
+                bset![outpoint(1)],
+                bmap! {},
+                allocations(&[(3, u64::MAX), (4, 1)])
+            ),
+            Err(Error::OutputsOverflow)
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn outputs_near_max(payment in u64::MAX / 2..=u64::MAX, change in u64::MAX / 2..=u64::MAX) {
+            let asset = asset();
+            let input = outpoint(0);
+            let beneficiary = SealEndpoint::from(seal(0));
+            let result = asset.transfer(
+                bset![input],
+                bmap! { beneficiary => payment },
+                bmap! { seal(0) => change },
+            );
+            match payment.checked_add(change) {
+                Some(outputs) => prop_assert_eq!(
+                    result,
+                    Err(Error::InputsNotEqualOutputs {
+                        inputs: 10,
+                        outputs,
+                        per_input: bmap! { input => 10 },
+                    })
+                ),
+                None => prop_assert_eq!(result, Err(Error::OutputsOverflow)),
+            }
+        }
     }
 }
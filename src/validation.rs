@@ -0,0 +1,527 @@
+//! Full validation of RGB121 consignments, covering schema requirements,
+//! anchors and single-use-seal closings in the witness transactions.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use bitcoin::{Transaction, Txid};
+use rgb::validation::Status;
+use rgb::{BundleId, Consignment, ConsignmentType, InmemConsignment, Validator, Validity};
+use wallet::onchain::ResolveTx;
+pub use wallet::onchain::TxResolverError;
+
+use crate::{asset, rules, Asset};
+
+/// Source of bitcoin transactions used during consignment validation to check
+/// anchors and closing of the single-use-seals.
+pub trait TxResolver {
+    /// Tries to find a transaction by its id ([`Txid`])
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError>;
+}
+
+/// In-memory transaction resolver, useful for testing and for validating
+/// consignments against a known set of transactions.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MemTxResolver(BTreeMap<Txid, Transaction>);
+
+impl MemTxResolver {
+    /// Constructs resolver knowing the provided set of transactions
+    pub fn with(txes: impl IntoIterator<Item = Transaction>) -> Self {
+        MemTxResolver(txes.into_iter().map(|tx| (tx.txid(), tx)).collect())
+    }
+
+    /// Adds transaction to the resolver
+    pub fn add_tx(&mut self, tx: Transaction) { self.0.insert(tx.txid(), tx); }
+}
+
+impl TxResolver for MemTxResolver {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.0
+            .get(&txid)
+            .cloned()
+            .ok_or_else(|| TxResolverError::with(txid))
+    }
+}
+
+/// Adaptor making any [`TxResolver`] usable by the RGB core validator
+struct Resolver<'resolver, R>(&'resolver R)
+where R: TxResolver + ?Sized;
+
+impl<'resolver, R> ResolveTx for Resolver<'resolver, R>
+where R: TxResolver + ?Sized
+{
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.0.resolve_tx(txid)
+    }
+}
+
+/// RGB121-specific consignment validation failures, complementing generic RGB
+/// validation [`Status`]. These are detected by off-chain wallet checks and
+/// are not reported by RGB core validation, which is unaware of RGB121 rules.
+#[derive(Clone, PartialEq, Eq, Debug, Display, From)]
+#[display(doc_comments)]
+pub enum Failure {
+    /// consignment does not contain a valid RGB121 asset: {0}
+    #[from]
+    Asset(asset::Error),
+
+    /// {0}
+    #[from]
+    Rule(rules::Error),
+
+    /// witness transaction {txid} does not commit to the state transition
+    /// bundle {bundle_id}
+    AnchorMismatch {
+        /// Id of the bundle which is not committed
+        bundle_id: BundleId,
+        /// Id of the witness transaction referenced by the anchor
+        txid: Txid,
+    },
+}
+
+/// Detailed report on consignment validation
+#[derive(Clone, Debug)]
+pub struct ValidationReport {
+    /// Status of generic RGB validation: schema checks, anchors and seal
+    /// closings
+    pub status: Status,
+
+    /// RGB121-specific failures
+    pub failures: Vec<Failure>,
+}
+
+impl ValidationReport {
+    /// Validates the consignment using the provided transaction resolver
+    pub fn with<T, R>(consignment: &InmemConsignment<T>, resolver: &R) -> Self
+    where
+        T: ConsignmentType,
+        R: TxResolver + ?Sized,
+    {
+        let mut failures = vec![];
+        if let Err(err) = Asset::try_from(consignment) {
+            failures.push(Failure::Asset(err));
+        }
+        failures.extend(rules::validate(consignment).into_iter().map(Failure::Rule));
+        // RGB core validator reports only errors happening during anchor
+        // verification, but not the negative verification result, so we have
+        // to re-check commitments of all resolved witness transactions
+        let contract_id = consignment.contract_id();
+        for (anchor, bundle) in consignment.anchored_bundles() {
+            let bundle_id = bundle.bundle_id();
+            let tx = match resolver.resolve_tx(anchor.txid) {
+                Ok(tx) => tx,
+                // Unresolved transactions are reported by the RGB core validator
+                Err(_) => continue,
+            };
+            if anchor.verify(contract_id, bundle_id.into(), tx) != Ok(true) {
+                failures.push(Failure::AnchorMismatch {
+                    bundle_id,
+                    txid: anchor.txid,
+                });
+            }
+        }
+        let status = Validator::validate(consignment, &Resolver(resolver));
+        ValidationReport { status, failures }
+    }
+
+    /// Returns overall validity of the consignment
+    pub fn validity(&self) -> Validity {
+        match self.status.validity() {
+            Validity::Valid | Validity::ValidExceptEndpoints if !self.failures.is_empty() => {
+                Validity::Invalid
+            }
+            validity => validity,
+        }
+    }
+
+    /// Detects whether the consignment is fully valid
+    pub fn is_valid(&self) -> bool { self.validity() == Validity::Valid }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "validity: {}", self.validity())?;
+        for failure in &self.failures {
+            writeln!(f, "failure: {}", failure)?;
+        }
+        for failure in &self.status.failures {
+            writeln!(f, "failure: {}", failure)?;
+        }
+        for txid in &self.status.unresolved_txids {
+            writeln!(f, "unresolved transaction: {}", txid)?;
+        }
+        for txid in &self.status.unmined_endpoint_txids {
+            writeln!(f, "unmined endpoint transaction: {}", txid)?;
+        }
+        for warning in &self.status.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        for info in &self.status.info {
+            writeln!(f, "info: {}", info)?;
+        }
+        Ok(())
+    }
+}
+
+impl Asset {
+    /// Runs full RGB validation of the consignment, including schema checks,
+    /// verification of anchors and single-use-seal closings, resolving witness
+    /// transactions with the provided `resolver`.
+    ///
+    /// Returns asset information if the consignment is valid, or a detailed
+    /// report listing all detected failures otherwise.
+    #[allow(clippy::result_large_err)]
+    pub fn validate_consignment<T, R>(
+        consignment: &InmemConsignment<T>,
+        resolver: &R,
+    ) -> Result<Asset, ValidationReport>
+    where
+        T: ConsignmentType,
+        R: TxResolver + ?Sized,
+    {
+        let report = ValidationReport::with(consignment, resolver);
+        if !report.is_valid() {
+            return Err(report);
+        }
+        Asset::try_from(consignment).map_err(|_| report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::Wrapper;
+    use bitcoin::{OutPoint, Script};
+    use rgb::fungible::allocation::AllocatedValue;
+    use rgb::{
+        seal, secp256k1zkp, value, Assignment, AtomicValue, Contract, Genesis, Node,
+        ParentOwnedRights, StateTransfer, Transition, TypedAssignments,
+    };
+    use seals::txout::CloseMethod;
+
+    use super::*;
+    use crate::schema::TransitionType;
+    use crate::test_helpers::{self, outpoint, Issuance};
+    use crate::{FieldType, OwnedRightType};
+
+    const CHANGE_UTXO: &str = "7e4a8f5eb42c7e0b0b2bd8d8b3e0c8b9e52aa1e46fd2b0b3d1a04da6f0cb3f51:1";
+
+    fn contract() -> Contract { test_helpers::contract(&[(0, 10)]) }
+
+    fn change() -> seal::Revealed {
+        seal::Revealed::new(
+            CloseMethod::OpretFirst,
+            OutPoint::from_str(CHANGE_UTXO).unwrap(),
+        )
+    }
+
+    /// Builds transfer consignment spending genesis allocation, returning it
+    /// together with the witness transaction
+    fn transfer(contract: &Contract) -> (StateTransfer, Transaction) {
+        let asset = Asset::try_from(contract).unwrap();
+        let outpoint = outpoint(0);
+        let transition = asset
+            .transfer(bset![outpoint], bmap! {}, bmap! { change() => 10 })
+            .unwrap();
+        anchor(contract, transition)
+    }
+
+    /// Builds engraving transition spending genesis allocation and assigning
+    /// `amount` to the change seal
+    fn engraving(contract: &Contract, amount: AtomicValue) -> Transition {
+        let asset = Asset::try_from(contract).unwrap();
+        let coins = asset.outpoint_coins(outpoint(0));
+        let mut parent = ParentOwnedRights::default();
+        for coin in &coins {
+            parent
+                .entry(coin.outpoint.node_id)
+                .or_insert_with(|| empty!())
+                .entry(OwnedRightType::Assets.into())
+                .or_insert_with(|| empty!())
+                .push(coin.outpoint.no);
+        }
+        let assignments = type_map! {
+            OwnedRightType::Assets => TypedAssignments::zero_balanced(
+                coins.into_iter().map(|coin| coin.state).collect(),
+                bmap! { change() => amount },
+                empty!(),
+            )
+        };
+        Transition::with(
+            TransitionType::Engraving,
+            empty!(),
+            empty!(),
+            assignments.into(),
+            empty!(),
+            parent,
+        )
+    }
+
+    /// Asset with 10 units and inflation right allowing to issue 5 units on
+    /// the output 0
+    fn inflatable() -> Contract {
+        Issuance {
+            inflation: vec![(0, 5)],
+            ..Issuance::with(&[(0, 10)])
+        }
+        .contract()
+    }
+
+    /// Builds secondary issue transition closing the inflation right of the
+    /// [`inflatable`] contract, declaring `issued` supply and assigning
+    /// `amount` to the change seal
+    fn issue(contract: &Contract, issued: AtomicValue, amount: AtomicValue) -> Transition {
+        let asset = Asset::try_from(contract).unwrap();
+        let mut parent = ParentOwnedRights::default();
+        for right in asset.outpoint_inflation(outpoint(0)) {
+            parent
+                .entry(right.outpoint.node_id)
+                .or_insert_with(|| empty!())
+                .entry(OwnedRightType::Inflation.into())
+                .or_insert_with(|| empty!())
+                .push(right.outpoint.no);
+        }
+        let assignments = type_map! {
+            OwnedRightType::Assets => TypedAssignments::zero_balanced(
+                vec![value::Revealed {
+                    value: issued,
+                    blinding: secp256k1zkp::key::ONE_KEY.into(),
+                }],
+                bmap! { change() => amount },
+                empty!(),
+            ),
+            OwnedRightType::Inflation => TypedAssignments::Value(vec![])
+        };
+        Transition::with(
+            TransitionType::Issue,
+            type_map! { FieldType::IssuedSupply => field!(U64, issued) }.into(),
+            empty!(),
+            assignments.into(),
+            empty!(),
+            parent,
+        )
+    }
+
+    /// Anchors the transition to a witness transaction spending genesis UTXO,
+    /// returning consignment containing it together with the witness
+    /// transaction
+    fn anchor(contract: &Contract, transition: Transition) -> (StateTransfer, Transaction) {
+        let (anchor, bundle, tx) = test_helpers::anchor(contract, transition, outpoint(0));
+        let mut consignment = StateTransfer::with(
+            contract.schema().clone(),
+            None,
+            contract.genesis().clone(),
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        consignment
+            .push_anchored_bundle(anchor, bundle.clone())
+            .unwrap();
+        consignment.push_seal_endpoint(bundle.bundle_id(), change().into());
+        (consignment, tx)
+    }
+
+    #[test]
+    fn genesis_valid() {
+        let contract = contract();
+        let asset = Asset::validate_consignment(&contract, &MemTxResolver::default()).unwrap();
+        assert_eq!(asset, Asset::try_from(&contract).unwrap());
+    }
+
+    #[test]
+    fn transfer_valid() {
+        let contract = contract();
+        let (consignment, witness_tx) = transfer(&contract);
+        let resolver = MemTxResolver::with([witness_tx]);
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+        assert!(Asset::validate_consignment(&consignment, &resolver).is_ok());
+    }
+
+    #[test]
+    fn transfer_unresolved() {
+        let contract = contract();
+        let (consignment, witness_tx) = transfer(&contract);
+        let report =
+            Asset::validate_consignment(&consignment, &MemTxResolver::default()).unwrap_err();
+        assert_eq!(report.validity(), Validity::ValidExceptEndpoints);
+        assert_eq!(
+            report.status.unmined_endpoint_txids,
+            vec![witness_tx.txid()]
+        );
+    }
+
+    #[test]
+    fn transfer_wrong_witness() {
+        let contract = contract();
+        let (consignment, mut witness_tx) = transfer(&contract);
+        let txid = witness_tx.txid();
+        witness_tx.output[0].script_pubkey = Script::new_op_return(&[1u8; 32]);
+        let resolver = MemTxResolver(bmap! { txid => witness_tx });
+        let report = ValidationReport::with(&consignment, &resolver);
+        assert_eq!(report.validity(), Validity::Invalid);
+        assert_eq!(report.failures, vec![Failure::AnchorMismatch {
+            bundle_id: consignment.anchored_bundles().next().unwrap().1.bundle_id(),
+            txid
+        }]);
+    }
+
+    #[test]
+    fn engraving_valid() {
+        let contract = contract();
+        let transition = engraving(&contract, 10);
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.failures, vec![]);
+    }
+
+    #[test]
+    fn engrave_valid() {
+        let contract = contract();
+        let asset = Asset::try_from(&contract).unwrap();
+        let outpoint = outpoint(0);
+        let transition = asset
+            .engrave(
+                bset![outpoint],
+                vec![vec![0xca, 0xfe]],
+                Some(1),
+                bmap! {},
+                bmap! { change() => 10 },
+            )
+            .unwrap();
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.failures, vec![]);
+    }
+
+    #[test]
+    fn engraving_changing_amount() {
+        let contract = contract();
+        for amount in [9, 11] {
+            let transition = engraving(&contract, amount);
+            let node_id = transition.node_id();
+            let (consignment, witness_tx) = anchor(&contract, transition);
+            let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+            assert_eq!(report.validity(), Validity::Invalid);
+            assert_eq!(report.failures, vec![Failure::Rule(
+                rules::Error::EngravingAmountChanged(node_id)
+            )]);
+            // The rule is not enforced by RGB core validation
+            assert!(report.status.failures.is_empty());
+        }
+    }
+
+    #[test]
+    fn issue_supply() {
+        let contract = inflatable();
+        let (consignment, witness_tx) = anchor(&contract, issue(&contract, 5, 5));
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.failures, vec![]);
+
+        let transition = issue(&contract, 5, 6);
+        let node_id = transition.node_id();
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.validity(), Validity::Invalid);
+        assert_eq!(report.failures, vec![Failure::Rule(
+            rules::Error::IssuedSupplyMismatch(node_id)
+        )]);
+    }
+
+    #[test]
+    fn inflate_valid() {
+        let contract = inflatable();
+        let asset = Asset::try_from(&contract).unwrap();
+        let allocations =
+            vec![AllocatedValue::from_str(&format!("5@opret1st:{}", CHANGE_UTXO)).unwrap()];
+        let transition = asset
+            .inflate(bset![outpoint(0)], bmap! {}, allocations)
+            .unwrap();
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+    }
+
+    #[test]
+    fn issue_exceeding_inflation() {
+        let contract = inflatable();
+        let transition = issue(&contract, 6, 6);
+        let node_id = transition.node_id();
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.failures, vec![Failure::Rule(
+            rules::Error::InflationMismatch(node_id)
+        )]);
+        // RGB core validation checks only the number of inflation rights
+        assert!(report.status.failures.is_empty());
+    }
+
+    #[test]
+    fn inflation_moved() {
+        let contract = inflatable();
+        let asset = Asset::try_from(&contract).unwrap();
+        let transition = asset
+            .blank_transition(bset![outpoint(0)], change())
+            .unwrap()
+            .unwrap();
+        let (consignment, witness_tx) = anchor(&contract, transition.clone());
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.validity(), Validity::Valid, "{}", report);
+
+        let mut owned_rights = transition.owned_rights().clone().into_inner();
+        owned_rights.insert(
+            OwnedRightType::Inflation.into(),
+            TypedAssignments::Value(vec![Assignment::Revealed {
+                seal: change(),
+                state: value::Revealed {
+                    value: 6,
+                    blinding: secp256k1zkp::key::ONE_KEY.into(),
+                },
+            }]),
+        );
+        let transition = Transition::with(
+            TransitionType::Transfer,
+            empty!(),
+            empty!(),
+            owned_rights.into(),
+            empty!(),
+            transition.parent_owned_rights().clone(),
+        );
+        let node_id = transition.node_id();
+        let (consignment, witness_tx) = anchor(&contract, transition);
+        let report = ValidationReport::with(&consignment, &MemTxResolver::with([witness_tx]));
+        assert_eq!(report.failures, vec![Failure::Rule(
+            rules::Error::InflationChanged(node_id)
+        )]);
+    }
+
+    #[test]
+    fn genesis_supply_mismatch() {
+        let contract = contract();
+        let genesis = contract.genesis();
+        let mut metadata = genesis.metadata().clone().into_inner();
+        metadata.insert(FieldType::IssuedSupply.into(), field!(U64, 11));
+        let genesis = Genesis::with(
+            genesis.schema_id(),
+            genesis.chain().clone(),
+            metadata.into(),
+            genesis.owned_rights().clone().into_inner(),
+            bset![],
+        );
+        let node_id = genesis.node_id();
+        let contract = Contract::with(
+            contract.schema().clone(),
+            None,
+            genesis,
+            empty!(),
+            empty!(),
+            empty!(),
+        );
+        let report = ValidationReport::with(&contract, &MemTxResolver::default());
+        assert_eq!(report.failures, vec![Failure::Rule(
+            rules::Error::IssuedSupplyMismatch(node_id)
+        )]);
+    }
+}